@@ -0,0 +1,245 @@
+use std::fmt;
+use std::str::FromStr;
+
+use base58::{FromBase58, ToBase58};
+use secp256k1::PublicKey;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+const HASH_LEN: usize = 32;
+const CHECKSUM_LEN: usize = 4;
+
+/// Which chain an address belongs to. Its version byte makes mainnet
+/// addresses start with `2` and testnet ones with `4`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    #[default]
+    Mainnet,
+    Testnet,
+}
+
+impl Network {
+    pub fn version(self) -> u8 {
+        match self {
+            Network::Mainnet => 0x2a,
+            Network::Testnet => 0x6f,
+        }
+    }
+
+    pub fn from_version(version: u8) -> Option<Self> {
+        [Network::Mainnet, Network::Testnet]
+            .into_iter()
+            .find(|network| network.version() == version)
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Network::Mainnet => write!(f, "mainnet"),
+            Network::Testnet => write!(f, "testnet"),
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mainnet" => Ok(Network::Mainnet),
+            "testnet" => Ok(Network::Testnet),
+            _ => Err(format!(
+                "unknown network {:?}, expected mainnet or testnet",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AddressError {
+    InvalidBase58,
+    InvalidLength(usize),
+    UnknownVersion(u8),
+    /// Almost always a typo.
+    InvalidChecksum,
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressError::InvalidBase58 => write!(f, "address is not valid base58"),
+            AddressError::InvalidLength(len) => {
+                write!(
+                    f,
+                    "address decodes to {} bytes instead of {}",
+                    len,
+                    Address::LEN
+                )
+            }
+            AddressError::UnknownVersion(version) => {
+                write!(f, "unknown address version {:#04x}", version)
+            }
+            AddressError::InvalidChecksum => write!(f, "address checksum does not match"),
+        }
+    }
+}
+
+impl std::error::Error for AddressError {}
+
+fn checksum(payload: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let hash = Sha256::digest(Sha256::digest(payload));
+    let mut checksum = [0u8; CHECKSUM_LEN];
+    checksum.copy_from_slice(&hash[..CHECKSUM_LEN]);
+    checksum
+}
+
+/// Base58Check address: a network version byte, the SHA-256 of the owner's
+/// compressed public key, and the first four bytes of the double SHA-256 of
+/// those two as a checksum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Address {
+    network: Network,
+    hash: [u8; HASH_LEN],
+}
+
+impl Address {
+    /// Decoded length: version, key hash and checksum.
+    pub const LEN: usize = 1 + HASH_LEN + CHECKSUM_LEN;
+
+    pub fn from_public_key(public_key: &PublicKey, network: Network) -> Self {
+        Self {
+            network,
+            hash: Sha256::digest(public_key.serialize()).into(),
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, AddressError> {
+        let bytes = s.from_base58().map_err(|_| AddressError::InvalidBase58)?;
+        if bytes.len() != Self::LEN {
+            return Err(AddressError::InvalidLength(bytes.len()));
+        }
+
+        let (payload, check) = bytes.split_at(1 + HASH_LEN);
+        if checksum(payload) != check {
+            return Err(AddressError::InvalidChecksum);
+        }
+
+        let network =
+            Network::from_version(payload[0]).ok_or(AddressError::UnknownVersion(payload[0]))?;
+        let mut hash = [0u8; HASH_LEN];
+        hash.copy_from_slice(&payload[1..]);
+
+        Ok(Self { network, hash })
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Whether `public_key` is the key this address pays to.
+    pub fn is_owned_by(&self, public_key: &PublicKey) -> bool {
+        self.hash == <[u8; HASH_LEN]>::from(Sha256::digest(public_key.serialize()))
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bytes = Vec::with_capacity(Self::LEN);
+        bytes.push(self.network.version());
+        bytes.extend_from_slice(&self.hash);
+        bytes.extend_from_slice(&checksum(&bytes));
+
+        write!(f, "{}", bytes.to_base58())
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1::{Secp256k1, SecretKey};
+
+    const BASE58: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+    fn address(network: Network) -> Address {
+        let secret = SecretKey::from_byte_array([7u8; 32]).unwrap();
+        Address::from_public_key(&secret.public_key(&Secp256k1::new()), network)
+    }
+
+    /// Base58Check encoding of arbitrary bytes, checksum included.
+    fn encode(payload: &[u8]) -> String {
+        let mut bytes = payload.to_vec();
+        bytes.extend_from_slice(&checksum(payload));
+        bytes.to_base58()
+    }
+
+    #[test]
+    fn bytes_and_version_round_trip() {
+        for (network, prefix) in [(Network::Mainnet, '2'), (Network::Testnet, '4')] {
+            let address = address(network);
+            let encoded = address.to_string();
+
+            assert!(encoded.starts_with(prefix));
+            assert_eq!(Address::parse(&encoded), Ok(address));
+            assert_eq!(Address::parse(&encoded).unwrap().network(), network);
+        }
+    }
+
+    #[test]
+    fn one_character_typo_fails() {
+        let encoded = address(Network::Mainnet).to_string();
+
+        for (i, original) in encoded.char_indices() {
+            for typo in BASE58.chars().filter(|&c| c != original) {
+                let mut mistyped = encoded.clone();
+                mistyped.replace_range(i..i + 1, typo.encode_utf8(&mut [0; 4]));
+
+                match Address::parse(&mistyped) {
+                    Err(AddressError::InvalidChecksum | AddressError::InvalidLength(_)) => {}
+                    other => panic!("{} parsed as {:?}", mistyped, other),
+                }
+            }
+        }
+
+        let mut mistyped = encoded.clone();
+        let middle = encoded.len() / 2;
+        let typo = if &encoded[middle..middle + 1] == "x" {
+            "y"
+        } else {
+            "x"
+        };
+        mistyped.replace_range(middle..middle + 1, typo);
+        assert_eq!(
+            Address::parse(&mistyped),
+            Err(AddressError::InvalidChecksum)
+        );
+    }
+
+    #[test]
+    fn malformed_addresses_give_the_matching_error() {
+        assert_eq!(Address::parse("0OIl"), Err(AddressError::InvalidBase58));
+
+        let short = [Network::Mainnet.version(); 1 + HASH_LEN - 1];
+        assert_eq!(
+            Address::parse(&encode(&short)),
+            Err(AddressError::InvalidLength(Address::LEN - 1))
+        );
+
+        let mut unknown = [0u8; 1 + HASH_LEN];
+        unknown[0] = 0x01;
+        assert_eq!(
+            Address::parse(&encode(&unknown)),
+            Err(AddressError::UnknownVersion(0x01))
+        );
+    }
+}
@@ -6,7 +6,8 @@ use crossterm::event::{Event, KeyCode, KeyEventKind};
 use ratatui::{
     layout::Rect,
     style::{Style, Stylize},
-    widgets::{Block, List, ListState},
+    text::Line,
+    widgets::{Block, List, ListState, Paragraph},
     Frame,
 };
 
@@ -116,3 +117,120 @@ impl Menu {
         None
     }
 }
+
+// Form
+pub struct FormField {
+    label: String,
+    value: String,
+    secret: bool,
+}
+
+impl FormField {
+    pub fn new(label: String) -> Self {
+        Self {
+            label,
+            value: String::new(),
+            secret: false,
+        }
+    }
+
+    /// A field whose value is drawn masked, for passphrases.
+    pub fn secret(label: String) -> Self {
+        Self {
+            secret: true,
+            ..Self::new(label)
+        }
+    }
+
+    fn display_value(&self) -> String {
+        if self.secret {
+            "*".repeat(self.value.chars().count())
+        } else {
+            self.value.clone()
+        }
+    }
+}
+
+pub enum FormEvent {
+    Submit,
+    Cancel,
+}
+
+pub struct Form {
+    title: String,
+    fields: Vec<FormField>,
+    selected: usize,
+}
+
+impl Form {
+    pub fn new(title: String, fields: Vec<FormField>) -> Self {
+        Self {
+            title,
+            fields,
+            selected: 0,
+        }
+    }
+
+    pub fn value(&self, index: usize) -> &str {
+        self.fields
+            .get(index)
+            .map(|field| field.value.as_str())
+            .unwrap_or_default()
+    }
+
+    pub fn clear(&mut self) {
+        for field in &mut self.fields {
+            field.value.clear();
+        }
+        self.selected = 0;
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect) {
+        let lines: Vec<Line> = self
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(index, field)| {
+                if index == self.selected {
+                    Line::from(vec![
+                        format!(">>{}: ", field.label).italic(),
+                        field.display_value().into(),
+                        "_".gray(),
+                    ])
+                } else {
+                    Line::from(format!("  {}: {}", field.label, field.display_value()))
+                }
+            })
+            .collect();
+
+        let paragraph = Paragraph::new(lines)
+            .block(Block::bordered().title(self.title.as_str().bold()))
+            .style(Style::new().white());
+
+        frame.render_widget(paragraph, area);
+    }
+
+    pub fn handle_events(&mut self, event: &Event) -> Option<FormEvent> {
+        match event {
+            Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Up | KeyCode::BackTab => {
+                    self.selected = self.selected.saturating_sub(1);
+                }
+                KeyCode::Down | KeyCode::Tab => {
+                    self.selected = (self.selected + 1).min(self.fields.len().saturating_sub(1));
+                }
+                KeyCode::Backspace => {
+                    self.fields.get_mut(self.selected)?.value.pop();
+                }
+                KeyCode::Char(c) => {
+                    self.fields.get_mut(self.selected)?.value.push(c);
+                }
+                KeyCode::Enter => return Some(FormEvent::Submit),
+                KeyCode::Esc => return Some(FormEvent::Cancel),
+                _ => {}
+            },
+            _ => {}
+        }
+        None
+    }
+}
@@ -1,15 +1,47 @@
 mod components;
+mod peers;
 mod states;
+mod sync;
 
-use crate::network::{run_client, run_server};
+use crate::config::Config;
 
 use components::Output;
 use states::{State, StateManager};
 
 use crossterm::event::{poll, read, Event, KeyCode, KeyEventKind};
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
 use ratatui::DefaultTerminal;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tokio::sync::mpsc;
+use tukecoin::{
+    address::Network,
+    blockchain::{Block, Blockchain, ChainEvent},
+    mempool::Mempool,
+    miner::Miner,
+    node::{load_or_create_identity, Node, NodeCommand, NodeConfig, NodeEvent, NodeHandle},
+    protocol::Message,
+    sync::HeaderSync,
+    transaction::Transaction,
+    wallet::Wallet,
+};
+
+const DATA_DIR: &str = "data";
+const KEYSTORE_FILE: &str = "wallet.dat";
+const NODE_KEY_FILE: &str = "node.key";
+
+fn keystore_path() -> PathBuf {
+    Path::new(DATA_DIR).join(KEYSTORE_FILE)
+}
+
+/// Where the blocks of `network` are stored. Mainnet keeps the top of the
+/// data directory, so existing stores stay where they are.
+fn chain_dir(network: Network) -> PathBuf {
+    match network {
+        Network::Mainnet => PathBuf::from(DATA_DIR),
+        network => Path::new(DATA_DIR).join(network.to_string()),
+    }
+}
 
 // Basics
 
@@ -18,23 +50,104 @@ pub enum Action {
     Quit,
     ChangeState(State),
     Print(String),
-    StartNetwork(u16),
+    StartNetwork,
     StopNetwork,
     NetworkMessage(String),
+    StartMining,
+    StopMining,
+    SubmitTransaction(Box<Transaction>),
+    NewReceiveAddress,
+    PeerConnected(PeerId, Multiaddr),
+    PeerDisconnected(PeerId),
+    AddPeer(Multiaddr),
+    RemovePeer(Multiaddr),
+    PeerMessage(PeerId, Message),
+    /// An inbound sync request, answered with `NodeCommand::Respond`.
+    SyncRequest(PeerId, u64, Message),
+    SyncResponse(PeerId, Message),
+    SyncFailed(PeerId),
+}
+
+impl From<NodeEvent> for Action {
+    fn from(event: NodeEvent) -> Self {
+        match event {
+            NodeEvent::Listening(address) => {
+                Action::NetworkMessage(format!("Listening on {}", address))
+            }
+            NodeEvent::PeerConnected(peer, address) => Action::PeerConnected(peer, address),
+            NodeEvent::PeerDisconnected(peer) => Action::PeerDisconnected(peer),
+            NodeEvent::Message(peer, message) => Action::PeerMessage(peer, message),
+            NodeEvent::SyncRequest(peer, id, message) => Action::SyncRequest(peer, id, message),
+            NodeEvent::SyncResponse(peer, message) => Action::SyncResponse(peer, message),
+            NodeEvent::SyncFailed(peer) => Action::SyncFailed(peer),
+            NodeEvent::Log(message) => Action::NetworkMessage(message),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MiningStats {
+    pub active: bool,
+    pub hashes: u64,
+    pub hashrate: f64,
+    pub last_block: Option<(u64, String)>,
+    pub blocks_found: u64,
+    pub rewards: u64,
+}
+
+#[derive(Default)]
+pub struct SyncProgress {
+    /// Peer the chain is being downloaded from, if any.
+    pub peer: Option<PeerId>,
+    pub target_height: u64,
+    pub downloading_blocks: bool,
 }
 
 pub struct Context {
     output: Output,
     pub action_sender: mpsc::UnboundedSender<Action>,
-    netwrok_port: u16,
+    pub config: Config,
+    /// Open connections and the remote address of each.
+    pub connected: Vec<(PeerId, Multiaddr)>,
+    pub network: Network,
+    pub blockchain: Blockchain,
+    pub mempool: Mempool,
+    pub wallet: Wallet,
+    pub mining: MiningStats,
+    pub sync: SyncProgress,
 }
 
 impl Context {
-    fn new(action_sender: mpsc::UnboundedSender<Action>) -> Self {
+    fn new(action_sender: mpsc::UnboundedSender<Action>, config: Config) -> Self {
+        let mut output = Output::new();
+        let network = config.network;
+        let dir = chain_dir(network);
+
+        let blockchain = match Blockchain::open(&dir, network) {
+            Ok((blockchain, report)) => {
+                if !report.is_clean() {
+                    output.add(format!("Repaired block storage: {:?}", report));
+                }
+                blockchain
+            }
+            Err(e) => {
+                output.add(format!("Could not open {}: {}", dir.display(), e));
+                Blockchain::new(network)
+            }
+        };
+
         Self {
-            output: Output::new(),
+            output,
             action_sender,
-            netwrok_port: 0,
+            config,
+            connected: Vec::new(),
+            network,
+            blockchain,
+            mempool: Mempool::new(network),
+            // Replaced by the keystore's wallet once it is unlocked.
+            wallet: Wallet::new(network),
+            mining: MiningStats::default(),
+            sync: SyncProgress::default(),
         }
     }
 }
@@ -46,20 +159,24 @@ pub struct App {
     ctx: Context,
     exit: bool,
     action_receiver: mpsc::UnboundedReceiver<Action>,
-    network_handle: Option<tokio::task::JoinHandle<()>>,
+    node: Option<NodeHandle>,
+    miner: Option<Miner>,
+    sync: Option<(PeerId, HeaderSync)>,
 }
 
 impl App {
-    pub fn new(terminal: DefaultTerminal) -> Self {
+    pub fn new(terminal: DefaultTerminal, config: Config) -> Self {
         let (action_sender, action_receiver) = mpsc::unbounded_channel();
 
         Self {
             terminal,
             state_manager: StateManager::new(),
-            ctx: Context::new(action_sender),
+            ctx: Context::new(action_sender, config),
             exit: false,
             action_receiver,
-            network_handle: None,
+            node: None,
+            miner: None,
+            sync: None,
         }
     }
 
@@ -78,7 +195,8 @@ impl App {
             self.run_routine()
         }
 
-        self.stop_network_task();
+        self.stop_network();
+        self.stop_mining();
     }
 
     fn run_routine(&mut self) {
@@ -86,6 +204,8 @@ impl App {
             self.handle_actions(action);
         }
 
+        self.poll_miner();
+
         let state = self.state_manager.current_state();
 
         let terminal = &mut self.terminal;
@@ -123,7 +243,18 @@ impl App {
     }
 
     fn handle_actions(&mut self, action: Action) {
-        self.print(format!("{:?}", action));
+        match &action {
+            Action::PeerMessage(peer, message) => {
+                self.print(format!("PeerMessage({}, {})", peer, message.name()));
+            }
+            Action::SyncRequest(peer, id, message) => {
+                self.print(format!("SyncRequest({}, {}, {})", peer, id, message.name()));
+            }
+            Action::SyncResponse(peer, message) => {
+                self.print(format!("SyncResponse({}, {})", peer, message.name()));
+            }
+            action => self.print(format!("{:?}", action)),
+        }
         match action {
             Action::Quit => {
                 self.exit = true;
@@ -137,63 +268,236 @@ impl App {
             Action::NetworkMessage(msg) => {
                 self.print(format!("[NET] {}", msg));
             }
-            Action::StartNetwork(port) => {
-                if port == 0 {
-                    self.start_client();
-                } else {
-                    self.start_server();
-                }
+            Action::StartNetwork => {
+                self.start_network();
             }
             Action::StopNetwork => {
-                self.stop_network_task();
+                self.stop_network();
+            }
+            Action::StartMining => {
+                if self.miner.is_none() {
+                    self.start_mining();
+                    self.print("Mining started");
+                }
+            }
+            Action::StopMining => {
+                self.stop_mining();
+            }
+            Action::SubmitTransaction(tx) => {
+                self.submit_transaction(*tx);
+            }
+            Action::NewReceiveAddress => {
+                let address = self.ctx.wallet.new_receive_address().to_string();
+                self.print(format!("Receive address: {}", address));
+            }
+            Action::PeerConnected(peer, address) => {
+                self.ctx.connected.push((peer, address));
+                self.start_sync(peer);
+            }
+            Action::PeerDisconnected(peer) => {
+                self.ctx
+                    .connected
+                    .retain(|(connected, _)| *connected != peer);
+                self.abort_sync(peer, "disconnected");
+            }
+            Action::AddPeer(address) => {
+                self.add_peer(address);
+            }
+            Action::RemovePeer(address) => {
+                self.remove_peer(address);
+            }
+            Action::PeerMessage(peer, message) => {
+                self.handle_peer_message(peer, message);
+            }
+            Action::SyncRequest(peer, id, message) => {
+                self.handle_sync_request(peer, id, message);
+            }
+            Action::SyncResponse(peer, message) => {
+                self.handle_sync_response(peer, message);
+            }
+            Action::SyncFailed(peer) => {
+                self.abort_sync(peer, "request failed");
             }
         }
     }
 
-    fn start_server(&mut self) {
-        let sender = self.ctx.action_sender.clone();
+    /// Pools a transaction made by this wallet and broadcasts it to peers.
+    fn submit_transaction(&mut self, tx: Transaction) {
+        let ctx = &mut self.ctx;
+        let message = Message::NewTransaction(Box::new(tx.clone()));
+
+        match ctx.mempool.add(tx, ctx.blockchain.ledger()) {
+            Ok(hash) => {
+                self.print(format!(
+                    "Transaction {} added to mempool",
+                    hex::encode(hash)
+                ));
+                self.broadcast(message);
+            }
+            Err(e) => {
+                ctx.wallet.sync(ctx.blockchain.ledger());
+                self.print(format!("Transaction rejected: {}", e));
+            }
+        }
+    }
 
-        if self.network_handle.is_some() {
-            self.print("Some network process is already started");
+    /// Remembers a peer for this session and dials it if the network is up.
+    fn add_peer(&mut self, address: Multiaddr) {
+        if self.ctx.config.bootstrap.contains(&address) {
+            self.print(format!("{} is already a peer", address));
             return;
         }
 
-        let handle = tokio::spawn(async move {
-            if let Err(e) = run_server(sender.clone()).await {
-                let _ = sender.send(Action::NetworkMessage(format!("Server error: {}", e)));
-            }
+        self.ctx.config.bootstrap.push(address.clone());
+        if let Some(node) = &self.node {
+            node.send(NodeCommand::Dial(address));
+        }
+    }
+
+    /// Forgets a peer and drops any connection to it. An address ending in
+    /// `/p2p/<peer id>` matches that peer wherever it connected from.
+    fn remove_peer(&mut self, address: Multiaddr) {
+        self.ctx.config.bootstrap.retain(|peer| *peer != address);
+
+        let peer_id = address.iter().find_map(|protocol| match protocol {
+            Protocol::P2p(peer_id) => Some(peer_id),
+            _ => None,
         });
+        let peers: Vec<PeerId> = self
+            .ctx
+            .connected
+            .iter()
+            .filter(|(peer, remote)| peer_id.map_or(*remote == address, |id| id == *peer))
+            .map(|(peer, _)| *peer)
+            .collect();
+
+        if let Some(node) = &self.node {
+            for peer in peers {
+                node.send(NodeCommand::Disconnect(peer));
+            }
+        }
+    }
 
-        self.network_handle = Some(handle);
+    fn start_mining(&mut self) {
+        let ctx = &mut self.ctx;
+        let transactions = ctx
+            .blockchain
+            .select_transactions(&ctx.mempool.transactions(), &ctx.wallet);
+        let template = ctx.blockchain.block_template(transactions, &ctx.wallet);
 
-        self.print("Server started");
+        self.miner = Some(Miner::start_default(template));
+        ctx.mining.active = true;
     }
 
-    fn start_client(&mut self) {
-        let sender = self.ctx.action_sender.clone();
+    fn stop_mining(&mut self) {
+        if let Some(miner) = self.miner.take() {
+            miner.cancel();
+            self.ctx.mining.active = false;
+            self.ctx.mining.hashrate = 0.0;
+            self.print("Mining stopped");
+        }
+    }
 
-        if self.network_handle.is_some() {
-            self.print("Some network process is already started");
+    /// Hands a found block to the chain and restarts the miner on the new
+    /// tip. A miner whose template no longer extends the tip is restarted too.
+    fn poll_miner(&mut self) {
+        let Some(miner) = &self.miner else {
             return;
+        };
+
+        let progress = miner.progress();
+        self.ctx.mining.hashes = progress.hashes;
+        self.ctx.mining.hashrate = progress.hashrate();
+
+        if let Some(block) = miner.try_result() {
+            self.miner = None;
+            self.handle_mined_block(block);
+            self.start_mining();
+        } else if miner.template().previous_hash != self.ctx.blockchain.tip().hash {
+            miner.cancel();
+            self.start_mining();
         }
+    }
+
+    fn handle_mined_block(&mut self, block: Block) {
+        // The coinbase amount plus the fees of everything else in the block.
+        let reward: u64 = block
+            .transactions
+            .iter()
+            .map(|tx| if tx.is_coinbase() { tx.amount } else { tx.fee })
+            .sum();
+        let height = block.height;
+        let hash = block.hash.clone();
+
+        match self.connect_block(block.clone()) {
+            Ok(events) => {
+                if events.contains(&ChainEvent::BlockConnected {
+                    height,
+                    hash: hash.clone(),
+                }) {
+                    let mining = &mut self.ctx.mining;
+                    mining.blocks_found += 1;
+                    mining.rewards += reward;
+                    mining.last_block = Some((height, hash));
+                }
 
-        let handle = tokio::spawn(async move {
-            if let Err(e) = run_client(sender.clone()).await {
-                let _ = sender.send(Action::NetworkMessage(format!("Server error: {}", e)));
+                self.announce_block(block);
             }
-        });
+            Err(e) => {
+                self.print(format!("Mined block rejected: {}", e));
+            }
+        }
+    }
 
-        self.network_handle = Some(handle);
+    fn start_network(&mut self) {
+        if self.node.is_some() {
+            self.print("The node is already running");
+            return;
+        }
+
+        let keypair = match load_or_create_identity(Path::new(DATA_DIR).join(NODE_KEY_FILE)) {
+            Ok(keypair) => keypair,
+            Err(e) => {
+                self.print(format!("Could not load the node key: {}", e));
+                return;
+            }
+        };
+        let config = NodeConfig {
+            listen: self.ctx.config.listen.clone(),
+            bootstrap: self.ctx.config.bootstrap.clone(),
+            tls: self.ctx.config.tls,
+            allowlist: self.ctx.config.allowlist.clone(),
+            ..NodeConfig::new(keypair)
+        };
+        let (node, handle, mut events) = match Node::new(&config) {
+            Ok(node) => node,
+            Err(e) => {
+                self.print(format!("Could not start the node: {}", e));
+                return;
+            }
+        };
+
+        let sender = self.ctx.action_sender.clone();
+        tokio::spawn(node.run());
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                if sender.send(event.into()).is_err() {
+                    break;
+                }
+            }
+        });
 
-        self.print("Client started");
+        self.node = Some(handle);
+        self.print("Node started");
     }
 
-    fn stop_network_task(&mut self) {
-        if let Some(handle) = &self.network_handle {
-            handle.abort();
+    fn stop_network(&mut self) {
+        if let Some(node) = self.node.take() {
+            node.send(NodeCommand::Shutdown);
 
-            self.print("Network process stopped");
-            self.network_handle = None;
+            self.ctx.connected.clear();
+            self.sync = None;
+            self.ctx.sync = SyncProgress::default();
         }
     }
 }
@@ -0,0 +1,147 @@
+use super::{App, Message, NodeCommand};
+
+use libp2p::PeerId;
+use tukecoin::{
+    blockchain::{Block, ChainError, ChainEvent},
+    encoding::Encode,
+    mempool::MempoolError,
+    protocol::{Inventory, MAX_MESSAGE_SIZE},
+};
+
+impl App {
+    pub(super) fn broadcast(&mut self, message: Message) {
+        match &self.node {
+            Some(node) if node.send(NodeCommand::Broadcast(message)) => {}
+            _ => self.print("Not connected, broadcast skipped"),
+        }
+    }
+
+    /// Floods a block, or just its hash when it is too large for floodsub.
+    /// Peers then fetch it by syncing from us.
+    pub(super) fn announce_block(&mut self, block: Block) {
+        if block.to_bytes().len() < MAX_MESSAGE_SIZE {
+            self.broadcast(Message::NewBlock(Box::new(block)));
+        } else {
+            self.broadcast(Message::Inv(vec![Inventory::Block(block.hash)]));
+        }
+    }
+
+    /// Adds a block to the chain and brings the mempool and wallet up to date.
+    pub(super) fn connect_block(&mut self, block: Block) -> Result<Vec<ChainEvent>, ChainError> {
+        let ctx = &mut self.ctx;
+        let events = ctx.blockchain.accept_block(block)?;
+
+        let blockchain = &ctx.blockchain;
+        let blocks = |hashes: &[String]| -> Vec<Block> {
+            hashes
+                .iter()
+                .filter_map(|hash| blockchain.get_block(hash).cloned())
+                .collect()
+        };
+        for event in &events {
+            match event {
+                ChainEvent::BlockConnected { hash, .. } => {
+                    if let Some(block) = blockchain.get_block(hash) {
+                        ctx.mempool.remove_mined(block, blockchain.ledger());
+                    }
+                }
+                ChainEvent::Reorganized {
+                    disconnected,
+                    connected,
+                    ..
+                } => ctx.mempool.reorganize(
+                    &blocks(disconnected),
+                    &blocks(connected),
+                    blockchain.ledger(),
+                ),
+                ChainEvent::SideBlock { .. } => {}
+            }
+        }
+        ctx.wallet.sync(ctx.blockchain.ledger());
+
+        for event in &events {
+            self.print(event.to_string());
+        }
+        Ok(events)
+    }
+
+    /// Syncs after `source` announced a block we cannot connect. Floodsub
+    /// relays messages, so the source may be a peer we have no connection
+    /// to; a directly connected peer is asked instead, having most likely
+    /// relayed the block.
+    fn sync_after_announcement(&mut self, source: PeerId) {
+        let connected = &self.ctx.connected;
+        let peer = if connected.iter().any(|(peer, _)| *peer == source) {
+            Some(source)
+        } else {
+            connected.first().map(|(peer, _)| *peer)
+        };
+
+        match peer {
+            Some(peer) => self.start_sync(peer),
+            None => self.print("No connected peer to sync from"),
+        }
+    }
+
+    /// Connects a block from a peer, syncing when the block does not connect
+    /// to any we know.
+    pub(super) fn receive_block(&mut self, peer: PeerId, block: Block) {
+        let height = block.height;
+
+        match self.connect_block(block) {
+            Ok(_) | Err(ChainError::KnownBlock) => {}
+            Err(ChainError::UnknownParent) => {
+                self.print(format!(
+                    "Block #{} from {} does not connect, syncing",
+                    height, peer
+                ));
+                self.sync_after_announcement(peer);
+            }
+            Err(e) => {
+                self.print(format!("Block #{} from {} rejected: {}", height, peer, e));
+            }
+        }
+    }
+
+    pub(super) fn handle_peer_message(&mut self, peer: PeerId, message: Message) {
+        match message {
+            Message::NewTransaction(tx) => {
+                let ctx = &mut self.ctx;
+                match ctx.mempool.add(*tx, ctx.blockchain.ledger()) {
+                    Ok(hash) => {
+                        self.print(format!("Transaction {} from {}", hex::encode(hash), peer));
+                    }
+                    Err(MempoolError::Duplicate) => {}
+                    Err(e) => {
+                        self.print(format!("Transaction from {} rejected: {}", peer, e));
+                    }
+                }
+            }
+            Message::NewBlock(block) => {
+                self.receive_block(peer, *block);
+            }
+            Message::Inv(items) => {
+                // Blocks can be too large to flood, so they come over sync.
+                // Transactions always fit and are flooded whole instead.
+                let blockchain = &self.ctx.blockchain;
+                let missing_block = items.iter().any(|item| {
+                    matches!(item, Inventory::Block(hash) if blockchain.get_block(hash).is_none())
+                });
+                if missing_block {
+                    self.sync_after_announcement(peer);
+                }
+            }
+            Message::GetData(_)
+            | Message::GetBlocks { .. }
+            | Message::Blocks(_)
+            | Message::GetHeaders { .. }
+            | Message::Headers(_) => {
+                self.print(format!(
+                    "Ignored {} from {}: chain data is only sent over sync requests",
+                    message.name(),
+                    peer
+                ));
+            }
+        }
+    }
+}
@@ -12,12 +12,8 @@ impl IdleState {
         Self {
             menu: Menu::new(vec![
                 MenuItem::new(
-                    "Start as a Client".to_string(),
-                    Action::ChangeState(State::Network(0)),
-                ),
-                MenuItem::new(
-                    "Start as a Server".to_string(),
-                    Action::ChangeState(State::Network(6969)),
+                    "Start node".to_string(),
+                    Action::ChangeState(State::Network),
                 ),
                 MenuItem::new("Exit".to_string(), Action::Quit),
             ]),
@@ -2,10 +2,13 @@ use super::{Action, AppState, Context, Menu};
 
 use crossterm::event::{Event, KeyCode, KeyEventKind};
 use ratatui::{
-    layout::{Constraint::Fill, Layout},
+    layout::{
+        Constraint::{Fill, Length},
+        Layout,
+    },
     style::Stylize,
     text::Line,
-    widgets::Block,
+    widgets::{Block, Paragraph},
     Frame,
 };
 
@@ -27,6 +30,18 @@ pub trait MenuState: AppState {
     }
 
     fn draw_menu(&mut self, frame: &mut Frame, ctx: &mut Context) {
+        self.draw_menu_with_details(frame, ctx, "", Vec::new());
+    }
+
+    /// Like `draw_menu`, with a titled panel of `details` above the output.
+    /// The panel is left out when there are no details.
+    fn draw_menu_with_details(
+        &mut self,
+        frame: &mut Frame,
+        ctx: &mut Context,
+        details_title: &str,
+        details: Vec<Line<'static>>,
+    ) {
         let title = self.get_title().to_string();
         let hints = self.hints();
         let menu = self.get_menu();
@@ -40,7 +55,19 @@ pub trait MenuState: AppState {
 
         menu.draw(frame, left_area);
 
-        ctx.output.draw(frame, right_area);
+        if details.is_empty() {
+            ctx.output.draw(frame, right_area);
+            return;
+        }
+
+        let vertical_layout = Layout::vertical([Length(details.len() as u16 + 2), Fill(1)]);
+        let [details_area, output_area] = vertical_layout.areas(right_area);
+
+        let details = Paragraph::new(details)
+            .block(Block::bordered().title(details_title.to_string().bold()));
+        frame.render_widget(details, details_area);
+
+        ctx.output.draw(frame, output_area);
     }
 
     fn handle_quit_and_menu(&mut self, event: &Event) -> Option<Action> {
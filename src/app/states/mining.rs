@@ -0,0 +1,79 @@
+use super::{Action, AppState, Context, Menu, MenuItem, MenuState, State};
+
+use crossterm::event::Event;
+use ratatui::{text::Line, Frame};
+use tukecoin::pow::difficulty;
+
+pub struct MiningState {
+    menu: Menu,
+}
+
+impl MiningState {
+    pub fn new() -> Self {
+        Self {
+            menu: Menu::new(vec![
+                MenuItem::new("Start mining".to_string(), Action::StartMining),
+                MenuItem::new("Stop mining".to_string(), Action::StopMining),
+                MenuItem::new("Exit".to_string(), Action::ChangeState(State::Network)),
+            ]),
+        }
+    }
+
+    fn details(ctx: &Context) -> Vec<Line<'static>> {
+        let mining = &ctx.mining;
+        let blockchain = &ctx.blockchain;
+
+        let last_block = match &mining.last_block {
+            Some((height, hash)) => format!("#{} {}", height, hash),
+            None => "-".to_string(),
+        };
+
+        vec![
+            Line::from(format!(
+                "Status:       {}",
+                if mining.active { "mining" } else { "stopped" }
+            )),
+            Line::from(format!("Height:       {}", blockchain.block_height)),
+            Line::from(format!(
+                "Difficulty:   {:.2}",
+                difficulty(blockchain.next_bits())
+            )),
+            Line::from(format!("Hashrate:     {:.0} H/s", mining.hashrate)),
+            Line::from(format!("Nonces tried: {}", mining.hashes)),
+            Line::from(format!("Last block:   {}", last_block)),
+            Line::from(format!("Blocks found: {}", mining.blocks_found)),
+            Line::from(format!("Rewards:      {}", mining.rewards)),
+        ]
+    }
+}
+
+impl AppState for MiningState {
+    fn get_parent(&self) -> Option<State> {
+        Some(State::Network)
+    }
+
+    fn on_enter(&mut self, _ctx: &mut Context) {}
+    fn on_exit(&mut self, _ctx: &mut Context) {
+        self.select_first();
+    }
+
+    fn draw(&mut self, frame: &mut Frame, ctx: &mut Context) {
+        let details = Self::details(ctx);
+        self.draw_menu_with_details(frame, ctx, "Miner", details);
+    }
+
+    fn handle_events(&mut self, event: &Event, ctx: &mut Context) {
+        if let Some(action) = self.handle_quit_and_menu(event) {
+            let _ = ctx.action_sender.send(action);
+        }
+    }
+}
+
+impl MenuState for MiningState {
+    fn get_title(&self) -> &str {
+        "Mining"
+    }
+    fn get_menu(&mut self) -> &mut Menu {
+        &mut self.menu
+    }
+}
@@ -1,40 +1,39 @@
 mod idle;
 mod menu_state;
+mod mining;
 mod network;
+mod peers;
+mod send;
 mod transacions;
+mod unlock;
 
 use crate::app::{
-    components::{Menu, MenuItem},
+    components::{Form, FormEvent, FormField, Menu, MenuItem},
     Action, Context,
 };
 use idle::IdleState;
 use menu_state::MenuState;
+use mining::MiningState;
 use network::NetworkState;
+use peers::PeersState;
+use send::SendState;
 use transacions::TransactionsState;
+use unlock::UnlockState;
 
 use crossterm::event::Event;
 use ratatui::Frame;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum State {
+    Unlock,
     Idle,
-    Network(u16),
+    Network,
+    Peers,
     Transactions,
+    Send,
     Mining,
 }
 
-impl PartialEq for State {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (State::Idle, State::Idle) => true,
-            (State::Network(_), State::Network(_)) => true,
-            (State::Transactions, State::Transactions) => true,
-            (State::Mining, State::Mining) => true,
-            _ => false,
-        }
-    }
-}
-
 // Traits
 pub trait AppState {
     fn get_parent(&self) -> Option<State>;
@@ -48,27 +47,38 @@ pub trait AppState {
 // Manager
 pub struct StateManager {
     state: State,
+    unlock: UnlockState,
     idle: IdleState,
     network: NetworkState,
+    peers: PeersState,
     transactions: TransactionsState,
+    send: SendState,
+    mining: MiningState,
 }
 
 impl StateManager {
     pub fn new() -> Self {
         Self {
-            state: State::Idle,
+            state: State::Unlock,
+            unlock: UnlockState::new(),
             idle: IdleState::new(),
             network: NetworkState::new(),
+            peers: PeersState::new(),
             transactions: TransactionsState::new(),
+            send: SendState::new(),
+            mining: MiningState::new(),
         }
     }
 
     fn get_state(&mut self, state: State) -> &mut dyn AppState {
         match state {
+            State::Unlock => &mut self.unlock,
             State::Idle => &mut self.idle,
-            State::Network(_) => &mut self.network,
+            State::Network => &mut self.network,
+            State::Peers => &mut self.peers,
             State::Transactions => &mut self.transactions,
-            _ => &mut self.idle,
+            State::Send => &mut self.send,
+            State::Mining => &mut self.mining,
         }
     }
 
@@ -77,22 +87,15 @@ impl StateManager {
     }
 
     fn build_path_to_root(&mut self, mut state: State) -> Vec<State> {
-        let mut path = vec![state.clone()];
+        let mut path = vec![state];
         while let Some(parent) = self.get_state(state).get_parent() {
-            path.push(parent.clone());
+            path.push(parent);
             state = parent;
         }
         path
     }
 
     pub fn transition(&mut self, new_state: State, ctx: &mut Context) {
-        match new_state {
-            State::Network(port) => {
-                ctx.netwrok_port = port;
-            }
-            _ => {}
-        }
-
         let mut current_path = self.build_path_to_root(self.state);
         let mut target_path = self.build_path_to_root(new_state);
 
@@ -1,7 +1,7 @@
 use super::{Action, AppState, Context, Menu, MenuItem, MenuState, State};
 
 use crossterm::event::Event;
-use ratatui::Frame;
+use ratatui::{text::Line, Frame};
 
 pub struct NetworkState {
     menu: Menu,
@@ -15,10 +15,35 @@ impl NetworkState {
                     "Make a transaction".to_string(),
                     Action::ChangeState(State::Transactions),
                 ),
+                MenuItem::new("Mining".to_string(), Action::ChangeState(State::Mining)),
+                MenuItem::new("Peers".to_string(), Action::ChangeState(State::Peers)),
                 MenuItem::new("Exit".to_string(), Action::ChangeState(State::Idle)),
             ]),
         }
     }
+
+    fn details(ctx: &Context) -> Vec<Line<'static>> {
+        let sync = &ctx.sync;
+        let height = ctx.blockchain.block_height;
+
+        let status = match sync.peer {
+            None => format!("Up to date at height {}", height),
+            Some(peer) if !sync.downloading_blocks => {
+                format!("Downloading headers from {}", peer)
+            }
+            Some(peer) => {
+                let percent = (height.min(sync.target_height) * 100)
+                    .checked_div(sync.target_height)
+                    .unwrap_or(100);
+                format!(
+                    "Downloading blocks from {}: {}/{} ({}%)",
+                    peer, height, sync.target_height, percent
+                )
+            }
+        };
+
+        vec![Line::from(status)]
+    }
 }
 
 impl AppState for NetworkState {
@@ -27,17 +52,17 @@ impl AppState for NetworkState {
     }
 
     fn on_enter(&mut self, ctx: &mut Context) {
-        let _ = ctx
-            .action_sender
-            .send(Action::StartNetwork(ctx.netwrok_port));
+        let _ = ctx.action_sender.send(Action::StartNetwork);
     }
 
     fn on_exit(&mut self, ctx: &mut Context) {
+        let _ = ctx.action_sender.send(Action::StopMining);
         let _ = ctx.action_sender.send(Action::StopNetwork);
     }
 
     fn draw(&mut self, frame: &mut Frame, ctx: &mut Context) {
-        self.draw_menu(frame, ctx);
+        let details = Self::details(ctx);
+        self.draw_menu_with_details(frame, ctx, "Sync", details);
     }
 
     fn handle_events(&mut self, event: &Event, ctx: &mut Context) {
@@ -0,0 +1,171 @@
+use super::{Action, AppState, Context, Form, FormEvent, FormField, State};
+
+use crossterm::event::{Event, KeyCode, KeyEventKind};
+use libp2p::{Multiaddr, PeerId};
+use ratatui::{
+    layout::{
+        Constraint::{Fill, Length},
+        Layout,
+    },
+    style::{Style, Stylize},
+    text::Line,
+    widgets::{Block, List, ListState, Paragraph},
+    Frame,
+};
+
+/// Configured peers and open connections, edited at runtime. Changes last
+/// until the app exits; the config file is left alone.
+pub struct PeersState {
+    form: Form,
+    list: ListState,
+    error: Option<String>,
+}
+
+impl PeersState {
+    pub fn new() -> Self {
+        Self {
+            form: Form::new(
+                "Add peer".to_string(),
+                vec![FormField::new("Multiaddr".to_string())],
+            ),
+            list: ListState::default(),
+            error: None,
+        }
+    }
+
+    fn hints(&self) -> Line<'static> {
+        Line::from(vec![
+            " Add ".into(),
+            "<Enter>".gray(),
+            " Peer ".into(),
+            "<↑↓>".gray(),
+            " Remove ".into(),
+            "<Del>".gray(),
+            " Back ".into(),
+            "<Esc>".gray(),
+            " ".into(),
+        ])
+        .centered()
+    }
+
+    /// Configured peers first, then connections that came in from others.
+    fn entries(ctx: &Context) -> Vec<(Multiaddr, Option<PeerId>)> {
+        let connected_to = |address: &Multiaddr| {
+            ctx.connected
+                .iter()
+                .find(|(_, remote)| remote == address)
+                .map(|(peer, _)| *peer)
+        };
+
+        let mut entries: Vec<(Multiaddr, Option<PeerId>)> = ctx
+            .config
+            .bootstrap
+            .iter()
+            .map(|address| (address.clone(), connected_to(address)))
+            .collect();
+        for (peer, remote) in &ctx.connected {
+            if !ctx.config.bootstrap.contains(remote) {
+                entries.push((remote.clone(), Some(*peer)));
+            }
+        }
+
+        entries
+    }
+
+    fn add(&mut self, ctx: &Context) {
+        let address = self.form.value(0).trim();
+        match address.parse::<Multiaddr>() {
+            Ok(address) => {
+                let _ = ctx.action_sender.send(Action::AddPeer(address));
+                self.form.clear();
+                self.error = None;
+            }
+            Err(e) => self.error = Some(format!("Not a valid multiaddr: {}", e)),
+        }
+    }
+
+    fn remove_selected(&mut self, ctx: &Context) {
+        let entries = Self::entries(ctx);
+        let Some((address, _)) = self.list.selected().and_then(|index| entries.get(index)) else {
+            return;
+        };
+
+        let _ = ctx.action_sender.send(Action::RemovePeer(address.clone()));
+        if self.list.selected() == Some(entries.len() - 1) {
+            self.list.select_previous();
+        }
+    }
+}
+
+impl AppState for PeersState {
+    fn get_parent(&self) -> Option<State> {
+        Some(State::Network)
+    }
+
+    fn on_enter(&mut self, _ctx: &mut Context) {
+        self.list.select(Some(0));
+    }
+    fn on_exit(&mut self, _ctx: &mut Context) {
+        self.form.clear();
+        self.error = None;
+    }
+
+    fn draw(&mut self, frame: &mut Frame, ctx: &mut Context) {
+        let main_block = Block::bordered()
+            .title("Peers".bold())
+            .title_bottom(self.hints());
+        let inner_area = main_block.inner(frame.area());
+        frame.render_widget(main_block, frame.area());
+
+        let horizontal_layout = Layout::horizontal([Fill(1), Fill(1)]);
+        let [left_area, right_area] = horizontal_layout.areas(inner_area);
+
+        let error_height = if self.error.is_some() { 3 } else { 0 };
+        let vertical_layout = Layout::vertical([Length(3), Length(error_height), Fill(1)]);
+        let [form_area, error_area, list_area] = vertical_layout.areas(left_area);
+
+        self.form.draw(frame, form_area);
+
+        if let Some(error) = &self.error {
+            let error = Paragraph::new(Line::from(error.clone().red())).block(Block::bordered());
+            frame.render_widget(error, error_area);
+        }
+
+        let items: Vec<Line> = Self::entries(ctx)
+            .into_iter()
+            .map(|(address, peer)| match peer {
+                Some(peer) => Line::from(format!("{} ({})", address, peer)),
+                None => Line::from(vec![address.to_string().into(), " (not connected)".gray()]),
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::bordered().title("Known peers".bold()))
+            .style(Style::new().white())
+            .highlight_style(Style::new().italic())
+            .highlight_symbol(">>");
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+
+        ctx.output.draw(frame, right_area);
+    }
+
+    fn handle_events(&mut self, event: &Event, ctx: &mut Context) {
+        if let Event::Key(key) = event {
+            if key.kind == KeyEventKind::Press {
+                match key.code {
+                    KeyCode::Up => return self.list.select_previous(),
+                    KeyCode::Down => return self.list.select_next(),
+                    KeyCode::Delete => return self.remove_selected(ctx),
+                    _ => {}
+                }
+            }
+        }
+
+        match self.form.handle_events(event) {
+            Some(FormEvent::Submit) => self.add(ctx),
+            Some(FormEvent::Cancel) => {
+                let _ = ctx.action_sender.send(Action::ChangeState(State::Network));
+            }
+            None => {}
+        }
+    }
+}
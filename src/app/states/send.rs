@@ -0,0 +1,274 @@
+use super::{Action, AppState, Context, Form, FormEvent, FormField, State};
+
+use crossterm::event::{Event, KeyCode, KeyEventKind};
+use ratatui::{
+    layout::{
+        Constraint::{Fill, Length},
+        Layout,
+    },
+    style::Stylize,
+    text::Line,
+    widgets::{Block, Paragraph},
+    Frame,
+};
+use tukecoin::address::{Address, AddressError};
+
+const RECIPIENT: usize = 0;
+const AMOUNT: usize = 1;
+const FEE: usize = 2;
+const MEMO: usize = 3;
+
+/// A validated form, waiting for the user to confirm it.
+struct Payment {
+    from: String,
+    recipient: String,
+    amount: u64,
+    fee: u64,
+    memo: Option<String>,
+}
+
+pub struct SendState {
+    form: Form,
+    pending: Option<Payment>,
+    error: Option<String>,
+}
+
+impl SendState {
+    pub fn new() -> Self {
+        Self {
+            form: Form::new(
+                "Payment".to_string(),
+                vec![
+                    FormField::new("Recipient".to_string()),
+                    FormField::new("Amount".to_string()),
+                    FormField::new("Fee".to_string()),
+                    FormField::new("Memo".to_string()),
+                ],
+            ),
+            pending: None,
+            error: None,
+        }
+    }
+
+    fn hints(&self) -> Line<'static> {
+        let hints = if self.pending.is_some() {
+            vec![
+                " Confirm ".into(),
+                "<Enter>".gray(),
+                " Edit ".into(),
+                "<Esc>".gray(),
+                " ".into(),
+            ]
+        } else {
+            vec![
+                " Field ".into(),
+                "<↑↓>".gray(),
+                " Review ".into(),
+                "<Enter>".gray(),
+                " Back ".into(),
+                "<Esc>".gray(),
+                " ".into(),
+            ]
+        };
+        Line::from(hints).centered()
+    }
+
+    /// Checks the form against the wallet's spendable balance.
+    fn validate(&self, ctx: &Context) -> Result<Payment, String> {
+        let recipient = self.form.value(RECIPIENT).trim().to_string();
+        match Address::parse(&recipient) {
+            Ok(address) if address.network() != ctx.wallet.network() => {
+                return Err(format!("Recipient is a {} address", address.network()));
+            }
+            Ok(_) => {}
+            Err(AddressError::InvalidChecksum) => {
+                return Err("Recipient checksum does not match, check for typos".to_string());
+            }
+            Err(e) => return Err(format!("Recipient is not a valid address: {}", e)),
+        }
+
+        let amount: u64 = match self.form.value(AMOUNT).trim().parse() {
+            Ok(amount) if amount > 0 => amount,
+            _ => return Err("Amount must be a positive whole number".to_string()),
+        };
+
+        let fee: u64 = match self.form.value(FEE).trim() {
+            "" => 0,
+            fee => fee
+                .parse()
+                .map_err(|_| "Fee must be a whole number".to_string())?,
+        };
+
+        let required = amount
+            .checked_add(fee)
+            .ok_or_else(|| "Amount plus fee is too large".to_string())?;
+
+        // A transaction has a single sender, so one key must cover it all.
+        // Sending to another of the wallet's own addresses is how funds
+        // spread over several keys are gathered onto one; sending a key's
+        // funds back to itself would only burn the fee.
+        let ledger = ctx.blockchain.ledger();
+        let available = |address: &str| ctx.mempool.available_balance(address, ledger);
+        let Some(from) = ctx
+            .wallet
+            .keys()
+            .iter()
+            .map(|key| key.address.clone())
+            .filter(|address| *address != recipient)
+            .find(|address| available(address) >= required)
+        else {
+            let total = ctx.wallet.available_balance(&ctx.mempool, ledger);
+            return Err(if total >= required {
+                format!(
+                    "No single address holds {}; send funds to {} first to gather them",
+                    required,
+                    ctx.wallet.address()
+                )
+            } else {
+                format!(
+                    "Insufficient funds: need {} but only {} available",
+                    required, total
+                )
+            });
+        };
+
+        let memo = self.form.value(MEMO).trim();
+        let memo = (!memo.is_empty()).then(|| memo.to_string());
+
+        Ok(Payment {
+            from,
+            recipient,
+            amount,
+            fee,
+            memo,
+        })
+    }
+
+    /// Signs the confirmed payment and hands it to the app for the mempool
+    /// and the network.
+    fn send(&mut self, payment: Payment, ctx: &mut Context) {
+        ctx.wallet
+            .sync_pending(&ctx.mempool, ctx.blockchain.ledger());
+
+        let Some(tx) = ctx.wallet.create_transaction(
+            &payment.from,
+            payment.recipient,
+            payment.amount,
+            payment.fee,
+            payment.memo,
+        ) else {
+            self.error = Some(format!("{} is not in this wallet", payment.from));
+            return;
+        };
+
+        let _ = ctx.action_sender.send(Action::Print(format!(
+            "Signed transaction #{} sending {} to {}",
+            tx.index, tx.amount, tx.recipient
+        )));
+        let _ = ctx
+            .action_sender
+            .send(Action::SubmitTransaction(Box::new(tx)));
+        let _ = ctx
+            .action_sender
+            .send(Action::ChangeState(State::Transactions));
+    }
+
+    fn details(&self, ctx: &Context) -> Vec<Line<'static>> {
+        let ledger = ctx.blockchain.ledger();
+        let mut lines = vec![
+            Line::from(format!("Receive address: {}", ctx.wallet.address())),
+            Line::from(format!(
+                "Balance: {} ({} available)",
+                ctx.wallet.balance(ledger),
+                ctx.wallet.available_balance(&ctx.mempool, ledger)
+            )),
+        ];
+
+        if let Some(payment) = &self.pending {
+            lines.push(Line::from(""));
+            lines.push(Line::from("Send this payment?".bold()));
+            lines.push(Line::from(format!("From: {}", payment.from)));
+            lines.push(Line::from(format!("To: {}", payment.recipient)));
+            lines.push(Line::from(format!(
+                "Amount: {} + fee {} = {}",
+                payment.amount,
+                payment.fee,
+                payment.amount + payment.fee
+            )));
+            if let Some(memo) = &payment.memo {
+                lines.push(Line::from(format!("Memo: {}", memo)));
+            }
+        } else if let Some(error) = &self.error {
+            lines.push(Line::from(""));
+            lines.push(Line::from(error.clone().red()));
+        }
+
+        lines
+    }
+}
+
+impl AppState for SendState {
+    fn get_parent(&self) -> Option<State> {
+        Some(State::Transactions)
+    }
+
+    fn on_enter(&mut self, _ctx: &mut Context) {}
+    fn on_exit(&mut self, _ctx: &mut Context) {
+        self.form.clear();
+        self.pending = None;
+        self.error = None;
+    }
+
+    fn draw(&mut self, frame: &mut Frame, ctx: &mut Context) {
+        let main_block = Block::bordered()
+            .title("Send".bold())
+            .title_bottom(self.hints());
+        let inner_area = main_block.inner(frame.area());
+        frame.render_widget(main_block, frame.area());
+
+        let horizontal_layout = Layout::horizontal([Fill(1), Fill(1)]);
+        let [left_area, right_area] = horizontal_layout.areas(inner_area);
+
+        let details = self.details(ctx);
+        let vertical_layout = Layout::vertical([Length(6), Length(details.len() as u16 + 2)]);
+        let [form_area, details_area] = vertical_layout.areas(left_area);
+
+        self.form.draw(frame, form_area);
+
+        let details = Paragraph::new(details).block(Block::bordered().title("Wallet".bold()));
+        frame.render_widget(details, details_area);
+
+        ctx.output.draw(frame, right_area);
+    }
+
+    fn handle_events(&mut self, event: &Event, ctx: &mut Context) {
+        if let Some(payment) = self.pending.take() {
+            match event {
+                Event::Key(key)
+                    if key.kind == KeyEventKind::Press && key.code == KeyCode::Enter =>
+                {
+                    self.send(payment, ctx);
+                }
+                Event::Key(key) if key.kind == KeyEventKind::Press && key.code == KeyCode::Esc => {}
+                _ => self.pending = Some(payment),
+            }
+            return;
+        }
+
+        match self.form.handle_events(event) {
+            Some(FormEvent::Submit) => match self.validate(ctx) {
+                Ok(payment) => {
+                    self.pending = Some(payment);
+                    self.error = None;
+                }
+                Err(e) => self.error = Some(e),
+            },
+            Some(FormEvent::Cancel) => {
+                let _ = ctx
+                    .action_sender
+                    .send(Action::ChangeState(State::Transactions));
+            }
+            None => {}
+        }
+    }
+}
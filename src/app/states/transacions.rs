@@ -11,11 +11,9 @@ impl TransactionsState {
     pub fn new() -> Self {
         Self {
             menu: Menu::new(vec![
-                MenuItem::new(
-                    "Send".to_string(),
-                    Action::Print("PICKLE RICK!".to_string()),
-                ),
-                MenuItem::new("Exit".to_string(), Action::ChangeState(State::Network(0))),
+                MenuItem::new("Send".to_string(), Action::ChangeState(State::Send)),
+                MenuItem::new("New receive address".to_string(), Action::NewReceiveAddress),
+                MenuItem::new("Exit".to_string(), Action::ChangeState(State::Network)),
             ]),
         }
     }
@@ -23,7 +21,7 @@ impl TransactionsState {
 
 impl AppState for TransactionsState {
     fn get_parent(&self) -> Option<State> {
-        Some(State::Network(0))
+        Some(State::Network)
     }
 
     fn on_enter(&mut self, _ctx: &mut Context) {}
@@ -0,0 +1,187 @@
+use super::{Action, AppState, Context, Form, FormEvent, FormField, State};
+use crate::app::keystore_path;
+
+use crossterm::event::Event;
+use ratatui::{
+    layout::{
+        Constraint::{Fill, Length},
+        Layout,
+    },
+    style::Stylize,
+    text::Line,
+    widgets::{Block, Paragraph, Wrap},
+    Frame,
+};
+use tukecoin::{address::Network, keystore::KeystoreError, wallet::Wallet};
+
+/// Asks for the keystore passphrase on startup, or for a new one when there
+/// is no keystore yet.
+pub struct UnlockState {
+    form: Form,
+    creating: bool,
+    error: Option<String>,
+}
+
+impl UnlockState {
+    pub fn new() -> Self {
+        let creating = !keystore_path().exists();
+
+        Self {
+            form: Self::form(creating),
+            creating,
+            error: None,
+        }
+    }
+
+    fn form(creating: bool) -> Form {
+        if creating {
+            Form::new(
+                "New wallet".to_string(),
+                vec![
+                    FormField::secret("Passphrase".to_string()),
+                    FormField::secret("Repeat".to_string()),
+                    FormField::new("Recovery phrase".to_string()),
+                ],
+            )
+        } else {
+            Form::new(
+                "Unlock wallet".to_string(),
+                vec![FormField::secret("Passphrase".to_string())],
+            )
+        }
+    }
+
+    fn hints(&self) -> Line<'static> {
+        Line::from(vec![
+            " Field ".into(),
+            "<↑↓>".gray(),
+            " Unlock ".into(),
+            "<Enter>".gray(),
+            " Quit ".into(),
+            "<Esc>".gray(),
+            " ".into(),
+        ])
+        .centered()
+    }
+
+    fn create(&self, network: Network) -> Result<Wallet, String> {
+        let passphrase = self.form.value(0);
+        if passphrase.is_empty() {
+            return Err("Passphrase must not be empty".to_string());
+        }
+        if passphrase != self.form.value(1) {
+            return Err("Passphrases do not match".to_string());
+        }
+
+        let phrase = self.form.value(2).trim();
+        let wallet = if phrase.is_empty() {
+            Wallet::new(network)
+        } else {
+            Wallet::from_phrase(phrase, network)
+                .map_err(|e| format!("Invalid recovery phrase: {}", e))?
+        };
+        wallet
+            .save(keystore_path(), passphrase)
+            .map_err(|e| format!("Could not save wallet: {}", e))?;
+        Ok(wallet)
+    }
+
+    fn unlock(&self, network: Network) -> Result<Wallet, String> {
+        Wallet::load(keystore_path(), self.form.value(0), network).map_err(|e| match e {
+            KeystoreError::WrongPassphrase => "Wrong passphrase".to_string(),
+            e => format!("Could not open wallet: {}", e),
+        })
+    }
+
+    fn details(&self) -> Vec<Line<'static>> {
+        let mut lines = vec![Line::from(if self.creating {
+            format!(
+                "No wallet found. Choose a passphrase to encrypt a new one at {}. \
+                 Enter a recovery phrase to restore an existing wallet instead.",
+                keystore_path().display()
+            )
+        } else {
+            format!("Enter the passphrase for {}.", keystore_path().display())
+        })];
+
+        if let Some(error) = &self.error {
+            lines.push(Line::from(""));
+            lines.push(Line::from(error.clone().red()));
+        }
+
+        lines
+    }
+}
+
+impl AppState for UnlockState {
+    fn get_parent(&self) -> Option<State> {
+        None
+    }
+
+    fn on_enter(&mut self, _ctx: &mut Context) {}
+    fn on_exit(&mut self, _ctx: &mut Context) {
+        self.creating = !keystore_path().exists();
+        self.form = Self::form(self.creating);
+        self.error = None;
+    }
+
+    fn draw(&mut self, frame: &mut Frame, ctx: &mut Context) {
+        let main_block = Block::bordered()
+            .title("TUKEcoin".bold())
+            .title_bottom(self.hints());
+        let inner_area = main_block.inner(frame.area());
+        frame.render_widget(main_block, frame.area());
+
+        let horizontal_layout = Layout::horizontal([Fill(1), Fill(1)]);
+        let [left_area, right_area] = horizontal_layout.areas(inner_area);
+
+        let vertical_layout = Layout::vertical([Length(5), Length(7)]);
+        let [form_area, details_area] = vertical_layout.areas(left_area);
+
+        self.form.draw(frame, form_area);
+
+        let details = Paragraph::new(self.details())
+            .wrap(Wrap { trim: true })
+            .block(Block::bordered().title("Wallet".bold()));
+        frame.render_widget(details, details_area);
+
+        ctx.output.draw(frame, right_area);
+    }
+
+    fn handle_events(&mut self, event: &Event, ctx: &mut Context) {
+        match self.form.handle_events(event) {
+            Some(FormEvent::Submit) => {
+                let result = if self.creating {
+                    self.create(ctx.network)
+                } else {
+                    self.unlock(ctx.network)
+                };
+
+                match result {
+                    Ok(mut wallet) => {
+                        if self.creating && self.form.value(2).trim().is_empty() {
+                            let _ = ctx.action_sender.send(Action::Print(format!(
+                                "Write down your recovery phrase: {}",
+                                wallet.mnemonic()
+                            )));
+                        }
+
+                        wallet.sync(ctx.blockchain.ledger());
+                        let _ = ctx.action_sender.send(Action::Print(format!(
+                            "Wallet unlocked, receive address {}",
+                            wallet.address()
+                        )));
+                        ctx.wallet = wallet;
+
+                        let _ = ctx.action_sender.send(Action::ChangeState(State::Idle));
+                    }
+                    Err(e) => self.error = Some(e),
+                }
+            }
+            Some(FormEvent::Cancel) => {
+                let _ = ctx.action_sender.send(Action::Quit);
+            }
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use super::{App, Message, NodeCommand, SyncProgress};
+
+use libp2p::PeerId;
+use tukecoin::{
+    blockchain::ChainError,
+    protocol::{Inventory, MAX_BLOCKS_PER_MESSAGE},
+    sync::{HeaderSync, SyncStep, BLOCKS_PER_REQUEST, MAX_HEADERS_PER_RESPONSE},
+};
+
+impl App {
+    fn request(&mut self, peer: PeerId, message: Message) {
+        if let Some(node) = &self.node {
+            node.send(NodeCommand::Request(peer, message));
+        }
+    }
+
+    /// Starts downloading the chain from `peer` unless a download is
+    /// already running.
+    pub(super) fn start_sync(&mut self, peer: PeerId) {
+        if self.sync.is_some() {
+            return;
+        }
+
+        self.print(format!("Syncing headers from {}", peer));
+        self.sync = Some((peer, HeaderSync::new()));
+        self.advance_sync();
+    }
+
+    /// Asks the sync peer for whatever the download needs next.
+    fn advance_sync(&mut self) {
+        let Some((peer, sync)) = &mut self.sync else {
+            return;
+        };
+        let peer = *peer;
+
+        let step = sync.next_step(&self.ctx.blockchain);
+        self.ctx.sync = SyncProgress {
+            peer: Some(peer),
+            target_height: sync.target_height(),
+            downloading_blocks: sync.is_downloading_blocks(),
+        };
+
+        match step {
+            SyncStep::Headers(locator) => self.request(peer, Message::GetHeaders { locator }),
+            SyncStep::Blocks(hashes) => {
+                let items = hashes.into_iter().map(Inventory::Block).collect();
+                self.request(peer, Message::GetData(items));
+            }
+            SyncStep::Done => {
+                self.print(format!(
+                    "Synced with {} at height {}",
+                    peer, self.ctx.blockchain.block_height
+                ));
+                self.sync = None;
+                self.ctx.sync = SyncProgress::default();
+            }
+        }
+    }
+
+    pub(super) fn abort_sync(&mut self, peer: PeerId, reason: &str) {
+        if !matches!(&self.sync, Some((sync_peer, _)) if *sync_peer == peer) {
+            return;
+        }
+
+        self.print(format!("Sync with {} aborted: {}", peer, reason));
+        self.sync = None;
+        self.ctx.sync = SyncProgress::default();
+    }
+
+    pub(super) fn handle_sync_response(&mut self, peer: PeerId, message: Message) {
+        let Some((sync_peer, sync)) = &mut self.sync else {
+            return;
+        };
+        if *sync_peer != peer {
+            return;
+        }
+
+        match message {
+            Message::Headers(headers) => {
+                if let Err(e) = sync.add_headers(headers, &self.ctx.blockchain) {
+                    return self.abort_sync(peer, &e.to_string());
+                }
+            }
+            Message::Blocks(blocks) => {
+                let blocks = match sync.take_blocks(blocks) {
+                    Ok(blocks) => blocks,
+                    Err(e) => return self.abort_sync(peer, &e.to_string()),
+                };
+
+                for block in blocks {
+                    let height = block.height;
+                    match self.connect_block(block) {
+                        Ok(_) | Err(ChainError::KnownBlock) => {}
+                        Err(e) => {
+                            let reason = format!("block #{} is invalid: {}", height, e);
+                            return self.abort_sync(peer, &reason);
+                        }
+                    }
+                }
+            }
+            other => {
+                let reason = format!("unexpected {} response", other.name());
+                return self.abort_sync(peer, &reason);
+            }
+        }
+
+        self.advance_sync();
+    }
+
+    /// Answers a peer's sync request from our chain.
+    pub(super) fn handle_sync_request(&mut self, peer: PeerId, id: u64, message: Message) {
+        let blockchain = &self.ctx.blockchain;
+        let response = match message {
+            Message::GetHeaders { locator } => Some(Message::Headers(
+                blockchain.headers_after(&locator, MAX_HEADERS_PER_RESPONSE),
+            )),
+            Message::GetBlocks { locator } => Some(Message::Blocks(
+                blockchain
+                    .blocks_after(&locator, MAX_BLOCKS_PER_MESSAGE)
+                    .to_vec(),
+            )),
+            Message::GetData(items) => Some(Message::Blocks(
+                items
+                    .iter()
+                    .filter_map(|item| match item {
+                        Inventory::Block(hash) => blockchain.get_block(hash).cloned(),
+                        Inventory::Transaction(_) => None,
+                    })
+                    .take(BLOCKS_PER_REQUEST)
+                    .collect(),
+            )),
+            other => {
+                self.print(format!("Refused {} request from {}", other.name(), peer));
+                None
+            }
+        };
+
+        if let Some(node) = &self.node {
+            node.send(NodeCommand::Respond(id, response));
+        }
+    }
+}
@@ -1,39 +1,267 @@
-use hex;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use chrono::Utc;
+use primitive_types::U256;
 use sha2::{Digest, Sha256};
 
-#[derive(Debug)]
+use crate::address::Network;
+use crate::encoding::{Decode, DecodeError, Decoder, Encode, Encoder};
+use crate::genesis::Genesis;
+use crate::ledger::{Ledger, LedgerError};
+use crate::merkle::{leaf_hash, MerkleProof, MerkleTree};
+use crate::pow::{block_work, meets_target, retarget, MAX_TARGET_BITS};
+use crate::storage::{BlockStore, RepairReport};
+use crate::transaction::Transaction;
+use crate::wallet::Wallet;
+
+/// Upper bound on the summed size of a block's transactions, in bytes.
+pub const MAX_BLOCK_SIZE: usize = 100_000;
+/// Blocks between difficulty adjustments.
+pub const DIFFICULTY_ADJUSTMENT_INTERVAL: usize = 10;
+/// Desired number of seconds between blocks.
+pub const TARGET_BLOCK_TIME: i64 = 30;
+/// A block must be newer than the median timestamp of this many predecessors.
+pub const MEDIAN_TIME_SPAN: usize = 11;
+/// How many seconds ahead of the local clock a block may be stamped.
+pub const MAX_FUTURE_BLOCK_TIME: i64 = 2 * 60 * 60;
+
+#[derive(Debug, Clone)]
 pub struct Block {
     pub height: u64,
-    pub data: String,
+    pub transactions: Vec<Transaction>,
+    pub merkle_root: String,
     pub previous_hash: String,
+    pub timestamp: i64,
+    /// Proof-of-work target in compact form, see `pow::bits_to_target`.
+    pub bits: u32,
     pub nonce: u64,
     pub hash: String,
 }
 
 impl Block {
-    pub fn new(height: u64, data: String, previous_hash: String, difficulty: usize) -> Self {
-        let mut block: Block = Block {
+    /// Builds and mines a block on the calling thread.
+    pub fn new(
+        height: u64,
+        transactions: Vec<Transaction>,
+        previous_hash: String,
+        timestamp: i64,
+        bits: u32,
+    ) -> Self {
+        let mut block = Self::template(height, transactions, previous_hash, timestamp, bits);
+        block.nonce = mine(&block, bits);
+        block.hash = hex::encode(block.calculate_hash(block.nonce));
+        block
+    }
+
+    /// Builds a block that still needs a nonce, see `miner::Miner`.
+    pub fn template(
+        height: u64,
+        transactions: Vec<Transaction>,
+        previous_hash: String,
+        timestamp: i64,
+        bits: u32,
+    ) -> Self {
+        let merkle_root = hex::encode(MerkleTree::from_transactions(&transactions).root());
+        Block {
             height,
-            data,
+            transactions,
+            merkle_root,
             previous_hash,
+            timestamp,
+            bits,
             nonce: 0,
             hash: "".to_string(),
-        };
-        block.nonce = mine(&block, difficulty);
-        block.hash = hex::encode(block.calculate_hash(block.nonce));
-        block
+        }
+    }
+
+    /// The block without its transactions, nonce and hash included.
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            height: self.height,
+            merkle_root: self.merkle_root.clone(),
+            previous_hash: self.previous_hash.clone(),
+            timestamp: self.timestamp,
+            bits: self.bits,
+            nonce: self.nonce,
+            hash: self.hash.clone(),
+        }
+    }
+
+    fn encode_header(&self, encoder: &mut Encoder, nonce: u64) {
+        encoder
+            .u64(self.height)
+            .str(&self.merkle_root)
+            .str(&self.previous_hash)
+            .i64(self.timestamp)
+            .u32(self.bits)
+            .u64(nonce);
     }
 
     pub fn calculate_hash(&self, nonce: u64) -> [u8; 32] {
-        Sha256::digest(format!(
-            "{}{}{}{}",
-            self.height, self.data, self.previous_hash, nonce
-        ))
-        .into()
+        let mut encoder = Encoder::new();
+        self.encode_header(&mut encoder, nonce);
+        Sha256::digest(encoder.finish()).into()
+    }
+
+    pub fn compute_merkle_root(&self) -> String {
+        hex::encode(MerkleTree::from_transactions(&self.transactions).root())
+    }
+
+    pub fn size(&self) -> usize {
+        self.transactions.iter().map(Transaction::size).sum()
+    }
+
+    /// Proof that the transaction with the given hash is part of this block,
+    /// checkable against `merkle_root` alone.
+    pub fn inclusion_proof(&self, tx_hash: &[u8; 32]) -> Option<MerkleProof> {
+        let index = self
+            .transactions
+            .iter()
+            .position(|tx| &leaf_hash(tx) == tx_hash)?;
+        MerkleTree::from_transactions(&self.transactions).proof(index)
+    }
+}
+
+/// The header followed by the transactions. The hash is not encoded; it is
+/// recomputed from the header when decoding.
+impl Encode for Block {
+    fn encode(&self, encoder: &mut Encoder) {
+        self.encode_header(encoder, self.nonce);
+        encoder.u32(self.transactions.len() as u32);
+        for tx in &self.transactions {
+            tx.encode(encoder);
+        }
+    }
+}
+
+impl Decode for Block {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        let header = BlockHeader::decode(decoder)?;
+
+        let count = decoder.u32()?;
+        let transactions = (0..count)
+            .map(|_| Transaction::decode(decoder))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Block {
+            height: header.height,
+            transactions,
+            merkle_root: header.merkle_root,
+            previous_hash: header.previous_hash,
+            timestamp: header.timestamp,
+            bits: header.bits,
+            nonce: header.nonce,
+            hash: header.hash,
+        })
+    }
+}
+
+/// Everything in a block but its transactions: enough to check its proof of
+/// work and where it links in before downloading the rest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockHeader {
+    pub height: u64,
+    pub merkle_root: String,
+    pub previous_hash: String,
+    pub timestamp: i64,
+    pub bits: u32,
+    pub nonce: u64,
+    pub hash: String,
+}
+
+impl BlockHeader {
+    pub fn calculate_hash(&self) -> [u8; 32] {
+        let mut encoder = Encoder::new();
+        self.encode(&mut encoder);
+        Sha256::digest(encoder.finish()).into()
+    }
+
+    /// Whether the hash matches the header and meets its own target.
+    pub fn has_valid_work(&self) -> bool {
+        let hash = self.calculate_hash();
+        self.hash == hex::encode(hash) && meets_target(&hash, self.bits)
+    }
+}
+
+/// Encoded exactly like the start of a block, so it hashes the same.
+impl Encode for BlockHeader {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder
+            .u64(self.height)
+            .str(&self.merkle_root)
+            .str(&self.previous_hash)
+            .i64(self.timestamp)
+            .u32(self.bits)
+            .u64(self.nonce);
+    }
+}
+
+impl Decode for BlockHeader {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        let mut header = BlockHeader {
+            height: decoder.u64()?,
+            merkle_root: decoder.str()?,
+            previous_hash: decoder.str()?,
+            timestamp: decoder.i64()?,
+            bits: decoder.u32()?,
+            nonce: decoder.u64()?,
+            hash: String::new(),
+        };
+        header.hash = hex::encode(header.calculate_hash());
+        Ok(header)
     }
 }
 
-pub fn mine(block: &Block, difficulty: usize) -> u64 {
+/// Compares transactions by fee per byte, highest first.
+pub(crate) fn by_fee_rate(a: &Transaction, b: &Transaction) -> Ordering {
+    let a_rate = a.fee as u128 * b.size() as u128;
+    let b_rate = b.fee as u128 * a.size() as u128;
+    b_rate.cmp(&a_rate)
+}
+
+/// Picks pending transactions by fee rate until `max_size` bytes are used,
+/// skipping any that `ledger` would reject. Candidates are revisited until
+/// nothing more fits, so a sender's later transactions can follow earlier,
+/// cheaper ones.
+pub fn select_transactions(
+    ledger: &Ledger,
+    pending: &[Transaction],
+    max_size: usize,
+) -> Vec<Transaction> {
+    let mut candidates: Vec<&Transaction> = pending.iter().collect();
+    candidates.sort_by(|a, b| by_fee_rate(a, b));
+
+    let mut staged = ledger.clone();
+    let mut selected = Vec::new();
+    let mut size = 0;
+
+    loop {
+        let mut skipped = Vec::new();
+
+        for tx in candidates.iter().copied() {
+            let tx_size = tx.size();
+            if size + tx_size > max_size || staged.apply_transaction(tx).is_err() {
+                skipped.push(tx);
+                continue;
+            }
+            size += tx_size;
+            selected.push(tx.clone());
+        }
+
+        if skipped.len() == candidates.len() {
+            break;
+        }
+        candidates = skipped;
+    }
+
+    selected
+}
+
+pub fn mine(block: &Block, bits: u32) -> u64 {
     let mut nonce: u64 = 0;
 
     let mut hash: [u8; 32];
@@ -41,7 +269,7 @@ pub fn mine(block: &Block, difficulty: usize) -> u64 {
     loop {
         hash = block.calculate_hash(nonce);
 
-        if hash.iter().take(difficulty).all(|&b| b == 0) {
+        if meets_target(&hash, bits) {
             break;
         }
         nonce += 1;
@@ -50,44 +278,1202 @@ pub fn mine(block: &Block, difficulty: usize) -> u64 {
     nonce
 }
 
+/// The header fields the difficulty and timestamp rules read, so the rules
+/// apply alike to full blocks and to the bare headers fetched during sync.
+pub trait ChainLink {
+    fn height(&self) -> u64;
+    fn timestamp(&self) -> i64;
+    fn bits(&self) -> u32;
+}
+
+impl ChainLink for Block {
+    fn height(&self) -> u64 {
+        self.height
+    }
+
+    fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    fn bits(&self) -> u32 {
+        self.bits
+    }
+}
+
+impl ChainLink for BlockHeader {
+    fn height(&self) -> u64 {
+        self.height
+    }
+
+    fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    fn bits(&self) -> u32 {
+        self.bits
+    }
+}
+
+/// Target required of the block that follows `ancestors`, which yields the
+/// parent first and then its predecessors.
+///
+/// Every `DIFFICULTY_ADJUSTMENT_INTERVAL` blocks the target is scaled by the
+/// time the last interval took relative to `TARGET_BLOCK_TIME`. In between,
+/// the previous target carries over.
+pub fn next_bits<'a, L: ChainLink + 'a>(mut ancestors: impl Iterator<Item = &'a L>) -> u32 {
+    let Some(last) = ancestors.next() else {
+        return MAX_TARGET_BITS;
+    };
+
+    let height = last.height() as usize + 1;
+    if !height.is_multiple_of(DIFFICULTY_ADJUSTMENT_INTERVAL) {
+        return last.bits();
+    }
+
+    let Some(first) = ancestors.nth(DIFFICULTY_ADJUSTMENT_INTERVAL - 2) else {
+        return last.bits();
+    };
+    let actual = last.timestamp() - first.timestamp();
+    let expected = (DIFFICULTY_ADJUSTMENT_INTERVAL as i64 - 1) * TARGET_BLOCK_TIME;
+
+    retarget(last.bits(), actual, expected)
+}
+
+/// Median timestamp of the last `MEDIAN_TIME_SPAN` of `ancestors`, which
+/// yields the newest block first.
+pub fn median_time_past<'a, L: ChainLink + 'a>(ancestors: impl Iterator<Item = &'a L>) -> i64 {
+    let mut timestamps: Vec<i64> = ancestors
+        .take(MEDIAN_TIME_SPAN)
+        .map(ChainLink::timestamp)
+        .collect();
+    timestamps.sort_unstable();
+    timestamps.get(timestamps.len() / 2).copied().unwrap_or(0)
+}
+
+/// Checks that need nothing but the block itself and the chain's network.
+fn check_block(block: &Block, network: Network) -> Result<(), ChainError> {
+    let hash = block.calculate_hash(block.nonce);
+    if block.hash != hex::encode(hash) {
+        return Err(ChainError::InvalidBlock("hash does not match the header"));
+    }
+    if !meets_target(&hash, block.bits) {
+        return Err(ChainError::InvalidBlock("hash is above the target"));
+    }
+
+    if block.merkle_root != block.compute_merkle_root() {
+        return Err(ChainError::InvalidBlock("merkle root does not match"));
+    }
+
+    // Repeating trailing transactions leaves the merkle root, and with it the
+    // hash, unchanged, so such a copy must not pass for the real block.
+    let mut seen = HashSet::new();
+    if !block
+        .transactions
+        .iter()
+        .all(|tx| seen.insert(leaf_hash(tx)))
+    {
+        return Err(ChainError::InvalidBlock("transaction is included twice"));
+    }
+
+    if !block.transactions.iter().all(Transaction::verify) {
+        return Err(ChainError::InvalidBlock("transaction signature is invalid"));
+    }
+    if block
+        .transactions
+        .iter()
+        .any(|tx| tx.network() != Some(network))
+    {
+        return Err(ChainError::InvalidBlock(
+            "transaction is for another network",
+        ));
+    }
+
+    match block.transactions.first() {
+        Some(coinbase) if coinbase.is_coinbase() && coinbase.index == block.height => {}
+        _ => {
+            return Err(ChainError::InvalidBlock(
+                "first transaction is not a coinbase",
+            ))
+        }
+    }
+    if block.transactions[1..].iter().any(Transaction::is_coinbase) {
+        return Err(ChainError::InvalidBlock("more than one coinbase"));
+    }
+
+    if block.size() > MAX_BLOCK_SIZE {
+        return Err(ChainError::InvalidBlock("block is too large"));
+    }
+
+    Ok(())
+}
+
+/// Checks against the branch the block extends. `ancestors` yields the
+/// parent first.
+fn check_context<'a>(
+    block: &Block,
+    ancestors: impl Iterator<Item = &'a Block> + Clone,
+) -> Result<(), ChainError> {
+    let Some(parent) = ancestors.clone().next() else {
+        return Err(ChainError::UnknownParent);
+    };
+
+    if block.previous_hash != parent.hash || block.height != parent.height + 1 {
+        return Err(ChainError::InvalidBlock("block does not follow its parent"));
+    }
+
+    check_timing(block, ancestors).map_err(ChainError::InvalidBlock)
+}
+
+/// Checks the timestamp and target of a block or header against the branch
+/// it extends, returning the rule it breaks. `ancestors` yields the parent
+/// first.
+pub fn check_timing<'a, L: ChainLink + 'a>(
+    link: &impl ChainLink,
+    ancestors: impl Iterator<Item = &'a L> + Clone,
+) -> Result<(), &'static str> {
+    if link.timestamp() <= median_time_past(ancestors.clone()) {
+        return Err("timestamp is before median time past");
+    }
+    if link.timestamp() > Utc::now().timestamp() + MAX_FUTURE_BLOCK_TIME {
+        return Err("timestamp is too far in the future");
+    }
+
+    if link.bits() != next_bits(ancestors) {
+        return Err("unexpected target");
+    }
+
+    Ok(())
+}
+
+/// Monetary policy: the block subsidy starts at `initial_reward`, halves every
+/// `halving_interval` blocks and stops once `max_supply` coins exist.
+#[derive(Debug, Clone)]
+pub struct Issuance {
+    pub initial_reward: u64,
+    pub halving_interval: u64,
+    pub max_supply: u64,
+}
+
+impl Default for Issuance {
+    fn default() -> Self {
+        Self {
+            initial_reward: 50,
+            halving_interval: 210_000,
+            max_supply: 21_000_000,
+        }
+    }
+}
+
+impl Issuance {
+    /// Scheduled subsidy for the block at `height`, ignoring the supply cap.
+    pub fn subsidy(&self, height: u64) -> u64 {
+        let halvings = height / self.halving_interval.max(1);
+        self.initial_reward
+            .checked_shr(halvings as u32)
+            .unwrap_or(0)
+    }
+
+    /// Subsidy for the block at `height` once `issued` coins already exist.
+    pub fn reward(&self, height: u64, issued: u64) -> u64 {
+        self.subsidy(height)
+            .min(self.max_supply.saturating_sub(issued))
+    }
+}
+
+#[derive(Debug)]
+pub enum ChainError {
+    KnownBlock,
+    UnknownParent,
+    InvalidBlock(&'static str),
+    Ledger(LedgerError),
+    Io(io::Error),
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::KnownBlock => write!(f, "block is already known"),
+            ChainError::UnknownParent => write!(f, "parent block is unknown"),
+            ChainError::InvalidBlock(reason) => write!(f, "invalid block: {}", reason),
+            ChainError::Ledger(e) => write!(f, "invalid block: {}", e),
+            ChainError::Io(e) => write!(f, "storage error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+impl From<LedgerError> for ChainError {
+    fn from(e: LedgerError) -> Self {
+        ChainError::Ledger(e)
+    }
+}
+
+impl From<io::Error> for ChainError {
+    fn from(e: io::Error) -> Self {
+        ChainError::Io(e)
+    }
+}
+
+/// Changes to the best chain caused by accepting a block.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainEvent {
+    /// The block extended the best chain.
+    BlockConnected { height: u64, hash: String },
+    /// The block was stored on a branch with less work than the best chain.
+    SideBlock { height: u64, hash: String },
+    /// A branch with more work replaced the blocks above `fork_height`.
+    Reorganized {
+        fork_height: u64,
+        disconnected: Vec<String>,
+        connected: Vec<String>,
+    },
+}
+
+impl fmt::Display for ChainEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainEvent::BlockConnected { height, hash } => {
+                write!(f, "Block #{} connected ({})", height, hash)
+            }
+            ChainEvent::SideBlock { height, hash } => {
+                write!(f, "Side-branch block #{} stored ({})", height, hash)
+            }
+            ChainEvent::Reorganized {
+                fork_height,
+                disconnected,
+                connected,
+            } => write!(
+                f,
+                "Reorganized at #{}: {} blocks disconnected, {} connected",
+                fork_height,
+                disconnected.len(),
+                connected.len()
+            ),
+        }
+    }
+}
+
+struct TreeEntry {
+    block: Block,
+    /// Cumulative work from genesis up to and including this block.
+    work: U256,
+    /// Whether the block has been on the best chain, i.e. passed the ledger.
+    connected: bool,
+}
+
+/// Every known block, arranged as a tree by `previous_hash`, with `chain`
+/// holding the branch with the most cumulative work and `ledger` its state.
 pub struct Blockchain {
     pub block_height: u64,
     pub chain: Vec<Block>,
-    pub difficulty: usize,
+    pub network: Network,
+    pub issuance: Issuance,
+    tree: HashMap<String, TreeEntry>,
+    ledger: Ledger,
+    storage: Option<BlockStore>,
 }
 
 impl Blockchain {
-    pub fn new(difficulty: usize) -> Self {
-        let genesis = Block::new(0, "Genesis Block".to_string(), "0".to_string(), difficulty);
+    /// A chain holding only the hard-coded genesis block of `network`.
+    pub fn new(network: Network) -> Self {
+        Self::with_issuance(network, Issuance::default())
+    }
+
+    pub fn with_issuance(network: Network, issuance: Issuance) -> Self {
+        let genesis = Genesis::for_network(network).block();
+        let mut blockchain = Self::from_blocks(vec![genesis], network, issuance);
+        blockchain.ledger = blockchain
+            .validate()
+            .expect("the hard-coded genesis block is valid");
+        blockchain
+    }
+
+    /// Wraps an already ordered chain without validating it.
+    fn from_blocks(chain: Vec<Block>, network: Network, issuance: Issuance) -> Self {
+        let mut tree = HashMap::new();
+        let mut work = U256::zero();
+        for block in &chain {
+            work += block_work(block.bits);
+            tree.insert(
+                block.hash.clone(),
+                TreeEntry {
+                    block: block.clone(),
+                    work,
+                    connected: true,
+                },
+            );
+        }
+
         Self {
-            block_height: 1,
-            chain: vec![genesis],
-            difficulty,
+            block_height: chain.len() as u64,
+            chain,
+            network,
+            issuance,
+            tree,
+            ledger: Ledger::new(),
+            storage: None,
         }
     }
 
-    pub fn add_block(&mut self, data: String) {
-        let previous_hash = self.chain.last().unwrap().hash.clone();
-        let block = Block::new(self.block_height, data, previous_hash, self.difficulty);
-        self.block_height += 1;
-        self.chain.push(block);
+    /// Loads the chain stored in `dir`, starting a new one if it is empty.
+    /// Blocks added afterwards are appended to the store. A store whose
+    /// chain starts from another network's genesis is refused.
+    pub fn open(dir: impl AsRef<Path>, network: Network) -> io::Result<(Self, RepairReport)> {
+        let (mut store, report) = BlockStore::open(dir)?;
+
+        let mut blockchain = if store.is_empty() {
+            let blockchain = Self::new(network);
+            store.append(&blockchain.chain[0])?;
+            blockchain
+        } else {
+            let mut blockchain =
+                Self::from_blocks(store.load_blocks()?, network, Issuance::default());
+            blockchain.ledger = blockchain
+                .validate()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            blockchain
+        };
+
+        blockchain.storage = Some(store);
+        Ok((blockchain, report))
     }
 
-    pub fn is_valid(&self) -> bool {
-        for i in 1..self.chain.len() {
-            let current = &self.chain[i];
-            let previous = &self.chain[i - 1];
+    pub fn tip(&self) -> &Block {
+        self.chain.last().unwrap()
+    }
+
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    pub fn get_block(&self, hash: &str) -> Option<&Block> {
+        self.tree.get(hash).map(|entry| &entry.block)
+    }
+
+    fn is_on_best_chain(&self, block: &Block) -> bool {
+        self.chain
+            .get(block.height as usize)
+            .is_some_and(|main| main.hash == block.hash)
+    }
+
+    /// The block with the given hash followed by its predecessors.
+    fn ancestors<'a>(&'a self, hash: &str) -> impl Iterator<Item = &'a Block> + Clone + 'a {
+        std::iter::successors(self.get_block(hash), move |block| {
+            self.get_block(&block.previous_hash)
+        })
+    }
+
+    /// Hashes of best-chain blocks from the tip back to genesis, dense at
+    /// first and then doubling the step, so a peer can find where its chain
+    /// and ours fork from a short list.
+    pub fn locator(&self) -> Vec<String> {
+        let mut locator = Vec::new();
+        let mut height = self.chain.len() - 1;
+        let mut step = 1;
+
+        loop {
+            locator.push(self.chain[height].hash.clone());
+            if height == 0 {
+                break;
+            }
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            height = height.saturating_sub(step);
+        }
+
+        locator
+    }
 
-            if current.previous_hash != previous.hash {
-                return false;
+    /// Height of the first `locator` hash on the best chain, or genesis if
+    /// none is.
+    fn fork_point(&self, locator: &[String]) -> usize {
+        locator
+            .iter()
+            .filter_map(|hash| self.get_block(hash))
+            .find(|block| self.is_on_best_chain(block))
+            .map_or(0, |block| block.height as usize)
+    }
+
+    /// Up to `limit` best-chain blocks following the fork point with `locator`.
+    pub fn blocks_after(&self, locator: &[String], limit: usize) -> &[Block] {
+        let start = self.fork_point(locator) + 1;
+        let end = (start + limit).min(self.chain.len());
+        &self.chain[start.min(end)..end]
+    }
+
+    /// Headers of the block `hash` and its ancestors, newest first, at most
+    /// `count` of them.
+    pub fn ancestor_headers(&self, hash: &str, count: usize) -> Vec<BlockHeader> {
+        self.ancestors(hash)
+            .take(count)
+            .map(Block::header)
+            .collect()
+    }
+
+    /// Headers of `blocks_after`.
+    pub fn headers_after(&self, locator: &[String], limit: usize) -> Vec<BlockHeader> {
+        self.blocks_after(locator, limit)
+            .iter()
+            .map(Block::header)
+            .collect()
+    }
+
+    /// Target the next block on the best chain must meet.
+    pub fn next_bits(&self) -> u32 {
+        next_bits(self.chain.iter().rev())
+    }
+
+    /// Total proof of work behind the best chain.
+    pub fn chain_work(&self) -> U256 {
+        self.tree[&self.tip().hash].work
+    }
+
+    /// Coins allocated at genesis plus those issued by the coinbase
+    /// transactions of the best chain.
+    pub fn issued(&self) -> u64 {
+        self.ledger.supply()
+    }
+
+    /// Chooses which of the pending transactions fit into the next block,
+    /// leaving room for the coinbase.
+    pub fn select_transactions(&self, pending: &[Transaction], miner: &Wallet) -> Vec<Transaction> {
+        let coinbase = miner.create_coinbase(self.block_height, 0);
+        select_transactions(
+            &self.ledger,
+            pending,
+            MAX_BLOCK_SIZE.saturating_sub(coinbase.size()),
+        )
+    }
+
+    /// Unmined block on top of the best chain with the given transactions,
+    /// prefixed by a coinbase paying the current reward to `miner`.
+    pub fn block_template(&self, transactions: Vec<Transaction>, miner: &Wallet) -> Block {
+        let reward = self.issuance.reward(self.block_height, self.issued());
+        let mut block_transactions = vec![miner.create_coinbase(self.block_height, reward)];
+        block_transactions.extend(transactions);
+
+        let timestamp = Utc::now()
+            .timestamp()
+            .max(median_time_past(self.chain.iter().rev()) + 1);
+
+        Block::template(
+            self.block_height,
+            block_transactions,
+            self.tip().hash.clone(),
+            timestamp,
+            self.next_bits(),
+        )
+    }
+
+    /// Mines a block template on the calling thread and adds it to the chain.
+    pub fn add_block(
+        &mut self,
+        transactions: Vec<Transaction>,
+        miner: &Wallet,
+    ) -> Result<Vec<ChainEvent>, ChainError> {
+        let mut block = self.block_template(transactions, miner);
+        block.nonce = mine(&block, block.bits);
+        block.hash = hex::encode(block.calculate_hash(block.nonce));
+
+        self.accept_block(block)
+    }
+
+    /// Adds a block to the tree. If its branch then has more work than the
+    /// best chain, the best chain switches to it.
+    ///
+    /// A side block is only checked on its own until its branch is switched
+    /// to, so receiving its hash again with other contents replaces it rather
+    /// than counting as known.
+    pub fn accept_block(&mut self, block: Block) -> Result<Vec<ChainEvent>, ChainError> {
+        if let Some(entry) = self.tree.get(&block.hash) {
+            if entry.connected || entry.block.to_bytes() == block.to_bytes() {
+                return Err(ChainError::KnownBlock);
+            }
+        }
+        let Some(parent_work) = self.tree.get(&block.previous_hash).map(|entry| entry.work) else {
+            return Err(ChainError::UnknownParent);
+        };
+
+        check_block(&block, self.network)?;
+        check_context(&block, self.ancestors(&block.previous_hash))?;
+
+        let work = parent_work + block_work(block.bits);
+        let height = block.height;
+        let hash = block.hash.clone();
+        self.tree.insert(
+            hash.clone(),
+            TreeEntry {
+                block,
+                work,
+                connected: false,
+            },
+        );
+
+        if work <= self.chain_work() {
+            return Ok(vec![ChainEvent::SideBlock { height, hash }]);
+        }
+
+        self.switch_to(&hash).inspect_err(|_| {
+            self.tree.remove(&hash);
+        })
+    }
+
+    /// Drops the block with `hash` and every block built on it.
+    fn discard_branch(&mut self, hash: &str) {
+        let mut discarded = vec![hash.to_string()];
+        while let Some(hash) = discarded.pop() {
+            self.tree.remove(&hash);
+            discarded.extend(
+                self.tree
+                    .values()
+                    .filter(|entry| entry.block.previous_hash == hash)
+                    .map(|entry| entry.block.hash.clone()),
+            );
+        }
+    }
+
+    /// Makes the branch ending at `tip_hash` the best chain: the ledger is
+    /// rolled back to the fork point and the new branch replayed on top. A
+    /// block the ledger rejects is discarded along with its descendants.
+    fn switch_to(&mut self, tip_hash: &str) -> Result<Vec<ChainEvent>, ChainError> {
+        let mut branch: Vec<Block> = self
+            .ancestors(tip_hash)
+            .take_while(|block| !self.is_on_best_chain(block))
+            .cloned()
+            .collect();
+        branch.reverse();
+
+        let fork_height = branch[0].height - 1;
+        let keep = fork_height as usize + 1;
+
+        let mut ledger = self.ledger.clone();
+        for block in self.chain[keep..].iter().rev() {
+            ledger.revert_block(block)?;
+        }
+        for block in &branch {
+            if let Err(e) = self.apply_to_ledger(&mut ledger, block) {
+                self.discard_branch(&block.hash);
+                return Err(e);
+            }
+        }
+
+        let connected: Vec<String> = branch.iter().map(|block| block.hash.clone()).collect();
+        let branch_events: Vec<ChainEvent> = branch
+            .iter()
+            .map(|block| ChainEvent::BlockConnected {
+                height: block.height,
+                hash: block.hash.clone(),
+            })
+            .collect();
+
+        // Switch in memory first, so a failed write can be undone.
+        let old_tail = self.chain.split_off(keep);
+        self.chain.extend(branch);
+        let old_ledger = std::mem::replace(&mut self.ledger, ledger);
+        self.block_height = self.chain.len() as u64;
+
+        if let Err(e) = self.store_from(keep) {
+            self.chain.truncate(keep);
+            self.chain.extend(old_tail);
+            self.ledger = old_ledger;
+            self.block_height = self.chain.len() as u64;
+            // Best effort: if this fails too, the store still holds a valid
+            // prefix of one branch, which `open` loads and sync extends.
+            let _ = self.store_from(keep);
+            return Err(e.into());
+        }
+
+        for hash in &connected {
+            if let Some(entry) = self.tree.get_mut(hash) {
+                entry.connected = true;
             }
+        }
+
+        if old_tail.is_empty() {
+            Ok(branch_events)
+        } else {
+            Ok(vec![ChainEvent::Reorganized {
+                fork_height,
+                disconnected: old_tail.into_iter().map(|block| block.hash).collect(),
+                connected,
+            }])
+        }
+    }
 
-            let recalculated = hex::encode(current.calculate_hash(current.nonce));
+    /// Rewrites the store from height `keep` on to match the best chain.
+    fn store_from(&mut self, keep: usize) -> io::Result<()> {
+        if let Some(storage) = &mut self.storage {
+            storage.truncate(keep)?;
+            for block in &self.chain[keep..] {
+                storage.append(block)?;
+            }
+        }
+        Ok(())
+    }
 
-            if current.hash != recalculated {
-                return false;
+    /// Applies a block to `ledger`, also checking that its coinbase does not
+    /// exceed the reward due at that point of the chain.
+    fn apply_to_ledger(&self, ledger: &mut Ledger, block: &Block) -> Result<(), ChainError> {
+        if let Some(coinbase) = block.transactions.first() {
+            if coinbase.amount > self.issuance.reward(block.height, ledger.supply()) {
+                return Err(ChainError::InvalidBlock(
+                    "coinbase pays more than the block reward",
+                ));
             }
         }
-        true
+        ledger.apply_block(block)?;
+        Ok(())
+    }
+
+    /// Checks the whole best chain from genesis and returns its ledger.
+    pub fn validate(&self) -> Result<Ledger, ChainError> {
+        let genesis = Genesis::for_network(self.network);
+        if self.chain.first().map(|block| block.hash.as_str()) != Some(genesis.hash) {
+            return Err(ChainError::InvalidBlock(
+                "chain does not start at the network's genesis",
+            ));
+        }
+        let mut ledger = genesis.ledger()?;
+
+        for i in 1..self.chain.len() {
+            let block = &self.chain[i];
+            check_block(block, self.network)?;
+            check_context(block, self.chain[..i].iter().rev())?;
+            self.apply_to_ledger(&mut ledger, block)?;
+        }
+
+        Ok(ledger)
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.validate().is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genesis::TESTNET_FAUCET_PHRASE;
+    use crate::pow::bits_to_target;
+
+    fn faucet() -> Wallet {
+        Wallet::from_phrase(TESTNET_FAUCET_PHRASE, Network::Testnet).unwrap()
+    }
+
+    fn mined(mut block: Block) -> Block {
+        block.nonce = mine(&block, block.bits);
+        block.hash = hex::encode(block.calculate_hash(block.nonce));
+        block
+    }
+
+    #[test]
+    fn block_round_trips_through_bytes() {
+        let mut chain = Blockchain::new(Network::Testnet);
+        let mut faucet = faucet();
+        let from = faucet.address().to_string();
+        let to = Wallet::new(Network::Testnet).address().to_string();
+        faucet.sync(chain.ledger());
+        let tx = faucet
+            .create_transaction(&from, to, 10, 1, Some("memo".to_string()))
+            .unwrap();
+        chain.add_block(vec![tx], &faucet).unwrap();
+
+        let block = chain.tip();
+        let bytes = block.to_bytes();
+        let decoded = Block::from_bytes(&bytes).unwrap();
+
+        assert_eq!(decoded.to_bytes(), bytes);
+        assert_eq!(decoded.hash, block.hash);
+        assert_eq!(decoded.header(), block.header());
+        assert_eq!(decoded.transactions.len(), 2);
+        assert!(check_block(&decoded, Network::Testnet).is_ok());
+
+        assert!(Block::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut padded = bytes;
+        padded.push(0);
+        assert_eq!(
+            Block::from_bytes(&padded).unwrap_err(),
+            DecodeError::TrailingBytes
+        );
+    }
+
+    #[test]
+    fn height_and_merkle_root_do_not_run_together() {
+        // Plain concatenation gave both of these the input "123".
+        let mut a = Block::template(1, Vec::new(), "0".to_string(), 0, MAX_TARGET_BITS);
+        a.merkle_root = "23".to_string();
+        let mut b = Block::template(12, Vec::new(), "0".to_string(), 0, MAX_TARGET_BITS);
+        b.merkle_root = "3".to_string();
+
+        assert_ne!(a.calculate_hash(0), b.calculate_hash(0));
+        assert_ne!(a.to_bytes(), b.to_bytes());
+    }
+
+    #[test]
+    fn header_hashes_like_its_block() {
+        let block = Genesis::for_network(Network::Testnet).block();
+        let header = BlockHeader::from_bytes(&block.header().to_bytes()).unwrap();
+
+        assert_eq!(header.hash, block.hash);
+        assert!(header.has_valid_work());
+    }
+
+    #[test]
+    fn block_with_repeated_transaction_is_rejected() {
+        let mut chain = Blockchain::new(Network::Testnet);
+        let mut faucet = faucet();
+        let from = faucet.address().to_string();
+        let to = Wallet::new(Network::Testnet).address().to_string();
+        faucet.sync(chain.ledger());
+
+        let a = faucet
+            .create_transaction(&from, to.clone(), 10, 1, None)
+            .unwrap();
+        let b = faucet.create_transaction(&from, to, 20, 1, None).unwrap();
+        let block = mined(chain.block_template(vec![a, b.clone()], &faucet));
+
+        let mut copy = block.clone();
+        copy.transactions.push(b);
+        assert_eq!(copy.compute_merkle_root(), block.merkle_root);
+        assert!(matches!(
+            chain.accept_block(copy),
+            Err(ChainError::InvalidBlock(_))
+        ));
+
+        assert!(chain.accept_block(block).is_ok());
+        assert_eq!(chain.ledger().balance(&from), 1_000_000 - 32);
+    }
+
+    #[test]
+    fn branch_with_more_work_replaces_the_best_chain() {
+        let mut ours = Blockchain::new(Network::Testnet);
+        let mut faucet = faucet();
+        let from = faucet.address().to_string();
+        let our_miner = Wallet::new(Network::Testnet);
+        faucet.sync(ours.ledger());
+        let tx = faucet
+            .create_transaction(&from, our_miner.address().to_string(), 10, 1, None)
+            .unwrap();
+        ours.add_block(vec![tx], &our_miner).unwrap();
+        let ours_tip = ours.tip().hash.clone();
+
+        let mut theirs = Blockchain::new(Network::Testnet);
+        let their_miner = Wallet::new(Network::Testnet);
+        theirs.add_block(Vec::new(), &their_miner).unwrap();
+        theirs.add_block(Vec::new(), &their_miner).unwrap();
+
+        let events = ours.accept_block(theirs.chain[1].clone()).unwrap();
+        assert!(matches!(
+            events[..],
+            [ChainEvent::SideBlock { height: 1, .. }]
+        ));
+        assert_eq!(ours.tip().hash, ours_tip);
+
+        let events = ours.accept_block(theirs.chain[2].clone()).unwrap();
+        assert_eq!(
+            events,
+            vec![ChainEvent::Reorganized {
+                fork_height: 0,
+                disconnected: vec![ours_tip.clone()],
+                connected: vec![theirs.chain[1].hash.clone(), theirs.chain[2].hash.clone()],
+            }]
+        );
+
+        assert_eq!(hashes(&ours.chain), hashes(&theirs.chain));
+        assert_eq!(ours.chain_work(), theirs.chain_work());
+        assert_eq!(ours.block_height, 3);
+        assert_eq!(ours.ledger().balance(&from), 1_000_000);
+        assert_eq!(ours.ledger().next_index(&from), 0);
+        assert_eq!(ours.ledger().balance(our_miner.address()), 0);
+        assert_eq!(ours.ledger().balance(their_miner.address()), 100);
+        assert_eq!(ours.issued(), theirs.issued());
+        assert!(ours.is_valid());
+
+        // The old tip stays known, so it is not downloaded again.
+        assert!(matches!(
+            ours.accept_block(ours.get_block(&ours_tip).unwrap().clone()),
+            Err(ChainError::KnownBlock)
+        ));
+    }
+
+    #[test]
+    fn ledger_revert_undoes_a_block() {
+        let mut chain = Blockchain::new(Network::Testnet);
+        let mut faucet = faucet();
+        let from = faucet.address().to_string();
+        let to = Wallet::new(Network::Testnet).address().to_string();
+        faucet.sync(chain.ledger());
+        let before = chain.ledger().clone();
+
+        let tx = faucet
+            .create_transaction(&from, to.clone(), 10, 3, None)
+            .unwrap();
+        chain.add_block(vec![tx], &faucet).unwrap();
+        let miner = chain.tip().transactions[0].recipient.clone();
+
+        let mut ledger = chain.ledger().clone();
+        assert_eq!(ledger.balance(&miner), 53);
+        ledger.revert_block(chain.tip()).unwrap();
+
+        for address in [&from, &to, &miner] {
+            assert_eq!(ledger.balance(address), before.balance(address));
+            assert_eq!(ledger.next_index(address), before.next_index(address));
+        }
+        assert_eq!(ledger.supply(), before.supply());
+    }
+
+    #[test]
+    fn branch_failing_the_ledger_is_discarded() {
+        let mut ours = Blockchain::new(Network::Testnet);
+        ours.add_block(Vec::new(), &Wallet::new(Network::Testnet))
+            .unwrap();
+        let ours_tip = ours.tip().hash.clone();
+
+        let theirs = Blockchain::new(Network::Testnet);
+        let miner = Wallet::new(Network::Testnet);
+        let mut template = theirs.block_template(Vec::new(), &miner);
+        template.transactions[0].amount += 1;
+        template.transactions[0].sign(&miner.keys()[0].private_key);
+        template.merkle_root = template.compute_merkle_root();
+        let overpaid = mined(template);
+        let child = mined(Block::template(
+            2,
+            vec![miner.create_coinbase(2, 50)],
+            overpaid.hash.clone(),
+            overpaid.timestamp + 1,
+            overpaid.bits,
+        ));
+
+        assert!(ours.accept_block(overpaid.clone()).is_ok());
+        assert!(matches!(
+            ours.accept_block(child.clone()),
+            Err(ChainError::InvalidBlock(_))
+        ));
+
+        assert_eq!(ours.tip().hash, ours_tip);
+        assert!(ours.get_block(&overpaid.hash).is_none());
+        assert!(ours.get_block(&child.hash).is_none());
+    }
+
+    #[test]
+    fn subsidy_halves_on_schedule() {
+        let issuance = Issuance {
+            initial_reward: 50,
+            halving_interval: 10,
+            max_supply: u64::MAX,
+        };
+
+        assert_eq!(issuance.subsidy(0), 50);
+        assert_eq!(issuance.subsidy(9), 50);
+        assert_eq!(issuance.subsidy(10), 25);
+        assert_eq!(issuance.subsidy(25), 12);
+        assert_eq!(issuance.subsidy(10 * 6), 0);
+        assert_eq!(issuance.subsidy(10 * 64), 0);
+        assert_eq!(issuance.subsidy(u64::MAX), 0);
+    }
+
+    #[test]
+    fn reward_stops_at_the_supply_cap() {
+        let issuance = Issuance {
+            initial_reward: 50,
+            halving_interval: 1_000,
+            max_supply: 120,
+        };
+
+        assert_eq!(issuance.reward(0, 0), 50);
+        assert_eq!(issuance.reward(2, 100), 20);
+        assert_eq!(issuance.reward(3, 120), 0);
+        assert_eq!(issuance.reward(3, 500), 0);
+
+        let mut chain = Blockchain::with_issuance(Network::Mainnet, issuance);
+        let miner = Wallet::new(Network::Mainnet);
+        for _ in 0..4 {
+            chain.add_block(Vec::new(), &miner).unwrap();
+        }
+        let rewards: Vec<u64> = chain.chain[1..]
+            .iter()
+            .map(|block| block.transactions[0].amount)
+            .collect();
+        assert_eq!(rewards, vec![50, 50, 20, 0]);
+        assert_eq!(chain.issued(), 120);
+        assert_eq!(chain.ledger().balance(miner.address()), 120);
+    }
+
+    #[test]
+    fn coinbase_paying_more_than_the_reward_is_rejected() {
+        let mut chain = Blockchain::new(Network::Testnet);
+        let miner = Wallet::new(Network::Testnet);
+
+        let mut template = chain.block_template(Vec::new(), &miner);
+        template.transactions[0].amount += 1;
+        template.transactions[0].sign(&miner.keys()[0].private_key);
+        template.merkle_root = template.compute_merkle_root();
+
+        assert!(matches!(
+            chain.accept_block(mined(template)),
+            Err(ChainError::InvalidBlock(
+                "coinbase pays more than the block reward"
+            ))
+        ));
+
+        chain.add_block(Vec::new(), &miner).unwrap();
+        assert_eq!(
+            chain
+                .ledger()
+                .balance(&chain.tip().transactions[0].recipient),
+            50
+        );
+    }
+
+    /// A funded sender for `select_transactions`.
+    fn sender(ledger: &mut Ledger) -> Wallet {
+        let wallet = Wallet::new(Network::Testnet);
+        ledger.credit(wallet.address(), 1_000).unwrap();
+        wallet
+    }
+
+    fn payment(wallet: &Wallet, index: u64, fee: u64) -> Transaction {
+        let key = &wallet.keys()[0];
+        let mut tx = Transaction::new(
+            index,
+            key.address.clone(),
+            key.address.clone(),
+            10,
+            fee,
+            None,
+            key.public_key,
+        );
+        tx.sign(&key.private_key);
+        tx
+    }
+
+    fn fees(transactions: &[Transaction]) -> Vec<u64> {
+        transactions.iter().map(|tx| tx.fee).collect()
+    }
+
+    #[test]
+    fn selection_prefers_higher_fee_rates() {
+        let mut ledger = Ledger::new();
+        let pending: Vec<Transaction> = [1, 10, 5]
+            .into_iter()
+            .map(|fee| payment(&sender(&mut ledger), 0, fee))
+            .collect();
+
+        let selected = select_transactions(&ledger, &pending, MAX_BLOCK_SIZE);
+        assert_eq!(fees(&selected), vec![10, 5, 1]);
+    }
+
+    #[test]
+    fn selection_stays_within_the_size_limit() {
+        let mut ledger = Ledger::new();
+        let pending: Vec<Transaction> = [1, 10, 5]
+            .into_iter()
+            .map(|fee| payment(&sender(&mut ledger), 0, fee))
+            .collect();
+        let two = pending[1].size() + pending[2].size();
+
+        let selected = select_transactions(&ledger, &pending, two);
+        assert_eq!(fees(&selected), vec![10, 5]);
+        assert!(select_transactions(&ledger, &pending, pending[0].size() - 1).is_empty());
+    }
+
+    #[test]
+    fn selection_keeps_a_senders_index_order() {
+        let mut ledger = Ledger::new();
+        let a = sender(&mut ledger);
+        let b = sender(&mut ledger);
+        // The sender's later transaction pays more, but needs the first one.
+        let pending = vec![payment(&a, 1, 100), payment(&b, 0, 50), payment(&a, 0, 1)];
+
+        let selected = select_transactions(&ledger, &pending, MAX_BLOCK_SIZE);
+        assert_eq!(fees(&selected), vec![50, 1, 100]);
+
+        let mut staged = ledger.clone();
+        for tx in &selected {
+            staged.apply_transaction(tx).unwrap();
+        }
+
+        let gap = vec![payment(&a, 1, 100), payment(&a, 2, 100)];
+        assert!(select_transactions(&ledger, &gap, MAX_BLOCK_SIZE).is_empty());
+    }
+
+    /// Mined block on the tip with the given timestamp and target.
+    fn block_at(chain: &Blockchain, miner: &Wallet, timestamp: i64, bits: u32) -> Block {
+        let height = chain.block_height;
+        let coinbase = miner.create_coinbase(height, chain.issuance.reward(height, chain.issued()));
+        mined(Block::template(
+            height,
+            vec![coinbase],
+            chain.tip().hash.clone(),
+            timestamp,
+            bits,
+        ))
+    }
+
+    fn invalid_reason(result: Result<Vec<ChainEvent>, ChainError>) -> &'static str {
+        match result {
+            Err(ChainError::InvalidBlock(reason)) => reason,
+            other => panic!("expected an invalid block, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn target_is_retargeted_every_interval() {
+        let mut chain = Blockchain::new(Network::Testnet);
+        let miner = Wallet::new(Network::Testnet);
+        let start = chain.tip().timestamp;
+
+        // Blocks six times faster than intended.
+        let spacing = TARGET_BLOCK_TIME / 6;
+        for height in 1..DIFFICULTY_ADJUSTMENT_INTERVAL as i64 {
+            assert_eq!(chain.next_bits(), MAX_TARGET_BITS);
+            let block = block_at(&chain, &miner, start + height * spacing, MAX_TARGET_BITS);
+            chain.accept_block(block).unwrap();
+        }
+
+        let expected = (DIFFICULTY_ADJUSTMENT_INTERVAL as i64 - 1) * TARGET_BLOCK_TIME;
+        let bits = retarget(MAX_TARGET_BITS, expected / 6, expected);
+        assert_eq!(chain.next_bits(), bits);
+        assert_eq!(
+            bits_to_target(bits),
+            bits_to_target(retarget(MAX_TARGET_BITS, expected / 4, expected))
+        );
+
+        let timestamp = start + DIFFICULTY_ADJUSTMENT_INTERVAL as i64 * spacing;
+        let easy = block_at(&chain, &miner, timestamp, MAX_TARGET_BITS);
+        assert_eq!(
+            invalid_reason(chain.accept_block(easy)),
+            "unexpected target"
+        );
+
+        chain
+            .accept_block(block_at(&chain, &miner, timestamp, bits))
+            .unwrap();
+        // Between adjustments the target carries over.
+        assert_eq!(chain.next_bits(), bits);
+    }
+
+    #[test]
+    fn timestamp_must_pass_the_median_of_recent_blocks() {
+        let mut chain = Blockchain::new(Network::Testnet);
+        let miner = Wallet::new(Network::Testnet);
+        let start = chain.tip().timestamp;
+
+        for offset in [100, 200, 150, 300] {
+            let block = block_at(&chain, &miner, start + offset, MAX_TARGET_BITS);
+            chain.accept_block(block).unwrap();
+        }
+
+        // The last five timestamps are start + 0, 100, 200, 150 and 300.
+        let median = start + 150;
+        assert_eq!(median_time_past(chain.chain.iter().rev()), median);
+
+        let stale = block_at(&chain, &miner, median, MAX_TARGET_BITS);
+        assert_eq!(
+            invalid_reason(chain.accept_block(stale)),
+            "timestamp is before median time past"
+        );
+
+        // Older than its parent is fine as long as it beats the median.
+        let block = block_at(&chain, &miner, median + 1, MAX_TARGET_BITS);
+        chain.accept_block(block).unwrap();
+    }
+
+    #[test]
+    fn timestamp_may_not_run_far_ahead_of_the_clock() {
+        let mut chain = Blockchain::new(Network::Testnet);
+        let miner = Wallet::new(Network::Testnet);
+        let now = Utc::now().timestamp();
+
+        let ahead = block_at(
+            &chain,
+            &miner,
+            now + MAX_FUTURE_BLOCK_TIME + 60,
+            MAX_TARGET_BITS,
+        );
+        assert_eq!(
+            invalid_reason(chain.accept_block(ahead)),
+            "timestamp is too far in the future"
+        );
+
+        let block = block_at(
+            &chain,
+            &miner,
+            now + MAX_FUTURE_BLOCK_TIME - 60,
+            MAX_TARGET_BITS,
+        );
+        chain.accept_block(block).unwrap();
+    }
+
+    #[test]
+    fn store_of_another_network_is_refused() {
+        let dir = std::env::temp_dir().join(format!("tukecoin-network-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let (mut chain, _) = Blockchain::open(&dir, Network::Testnet).unwrap();
+        chain
+            .add_block(Vec::new(), &Wallet::new(Network::Testnet))
+            .unwrap();
+        drop(chain);
+
+        let refused = Blockchain::open(&dir, Network::Mainnet).map(|_| ());
+        let reopened = Blockchain::open(&dir, Network::Testnet).map(|(chain, _)| chain.chain.len());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(refused.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(reopened.unwrap(), 2);
+    }
+
+    #[test]
+    fn reorganization_is_persisted() {
+        let dir = std::env::temp_dir().join(format!("tukecoin-reorg-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let (mut ours, _) = Blockchain::open(&dir, Network::Testnet).unwrap();
+        ours.add_block(Vec::new(), &Wallet::new(Network::Testnet))
+            .unwrap();
+
+        let mut theirs = Blockchain::new(Network::Testnet);
+        let miner = Wallet::new(Network::Testnet);
+        theirs.add_block(Vec::new(), &miner).unwrap();
+        theirs.add_block(Vec::new(), &miner).unwrap();
+        for block in &theirs.chain[1..] {
+            ours.accept_block(block.clone()).unwrap();
+        }
+        drop(ours);
+
+        let reopened = Blockchain::open(&dir, Network::Testnet);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let (reopened, report) = reopened.unwrap();
+
+        assert!(report.is_clean());
+        assert_eq!(hashes(&reopened.chain), hashes(&theirs.chain));
+        assert_eq!(reopened.ledger().balance(miner.address()), 100);
+    }
+
+    fn hashes(blocks: &[Block]) -> Vec<String> {
+        blocks.iter().map(|block| block.hash.clone()).collect()
+    }
+
+    #[test]
+    fn coinbase_for_another_network_is_rejected() {
+        let mut chain = Blockchain::new(Network::Mainnet);
+        let result = chain.add_block(Vec::new(), &Wallet::new(Network::Testnet));
+        assert_eq!(invalid_reason(result), "transaction is for another network");
+        assert_eq!(chain.chain.len(), 1);
+    }
+
+    #[test]
+    fn transaction_for_another_network_is_rejected() {
+        let mut chain = Blockchain::new(Network::Mainnet);
+        let mut faucet = faucet();
+        let from = faucet.address().to_string();
+        let to = Wallet::new(Network::Testnet).address().to_string();
+        let tx = faucet.create_transaction(&from, to, 10, 1, None).unwrap();
+
+        let result = chain.add_block(vec![tx], &Wallet::new(Network::Mainnet));
+        assert_eq!(invalid_reason(result), "transaction is for another network");
     }
 }
@@ -0,0 +1,212 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use libp2p::{Multiaddr, PeerId};
+use serde::Deserialize;
+use tukecoin::address::Network;
+
+const CONFIG_FILE: &str = "tukecoin.toml";
+const DEFAULT_LISTEN: &str = "/ip4/0.0.0.0/tcp/6969";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "invalid {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Parser)]
+#[command(about = "TUKEcoin node")]
+pub struct Cli {
+    /// Config file to read.
+    #[arg(long, default_value = CONFIG_FILE)]
+    pub config: PathBuf,
+
+    /// Chain to join, `mainnet` or `testnet`, replacing the configured one.
+    #[arg(long)]
+    pub network: Option<Network>,
+
+    /// Address to listen on, replacing the configured ones. May be repeated.
+    #[arg(long, value_name = "MULTIADDR")]
+    pub listen: Vec<Multiaddr>,
+
+    /// Peer to dial on start, in addition to the configured ones. May be
+    /// repeated.
+    #[arg(long, value_name = "MULTIADDR")]
+    pub bootstrap: Vec<Multiaddr>,
+
+    /// Offer TLS next to Noise.
+    #[arg(long)]
+    pub tls: bool,
+
+    /// Add a peer to the allowlist; once it has entries, no other peer may
+    /// connect. May be repeated.
+    #[arg(long, value_name = "PEER_ID")]
+    pub allow: Vec<PeerId>,
+}
+
+/// Where the node listens, whom it dials and whom it accepts, e.g.
+///
+/// ```toml
+/// network = "testnet"
+/// listen = ["/ip4/0.0.0.0/tcp/6969"]
+/// bootstrap = ["/ip4/192.168.178.118/tcp/6969"]
+/// tls = true
+/// allowlist = ["12D3KooW..."]
+/// ```
+///
+/// An empty allowlist lets every peer connect.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub network: Network,
+    pub listen: Vec<Multiaddr>,
+    pub bootstrap: Vec<Multiaddr>,
+    pub tls: bool,
+    pub allowlist: Vec<PeerId>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            network: Network::default(),
+            listen: vec![DEFAULT_LISTEN
+                .parse()
+                .expect("default listen address is valid")],
+            bootstrap: Vec::new(),
+            tls: false,
+            allowlist: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads `path`, falling back to the defaults when it does not exist.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text).map_err(|e| ConfigError::Parse(path.into(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(ConfigError::Io(path.into(), e)),
+        }
+    }
+
+    /// The config file named on the command line with the flags applied.
+    pub fn from_cli(cli: Cli) -> Result<Self, ConfigError> {
+        let mut config = Self::load(&cli.config)?;
+
+        if let Some(network) = cli.network {
+            config.network = network;
+        }
+        if !cli.listen.is_empty() {
+            config.listen = cli.listen;
+        }
+        for addr in cli.bootstrap {
+            if !config.bootstrap.contains(&addr) {
+                config.bootstrap.push(addr);
+            }
+        }
+        config.tls |= cli.tls;
+        for peer in cli.allow {
+            if !config.allowlist.contains(&peer) {
+                config.allowlist.push(peer);
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("tukecoin").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn flags_override_the_file_and_defaults_fill_the_rest() {
+        let dir = std::env::temp_dir().join(format!("tukecoin-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CONFIG_FILE);
+        let listed = PeerId::random();
+        fs::write(
+            &path,
+            format!(
+                "network = \"testnet\"\n\
+                 listen = [\"/ip4/127.0.0.1/tcp/1000\"]\n\
+                 bootstrap = [\"/ip4/10.0.0.1/tcp/6969\"]\n\
+                 allowlist = [\"{}\"]\n",
+                listed
+            ),
+        )
+        .unwrap();
+
+        let allowed = PeerId::random();
+        let config = Config::from_cli(parse(&[
+            "--config",
+            path.to_str().unwrap(),
+            "--network",
+            "mainnet",
+            "--listen",
+            "/ip4/127.0.0.1/tcp/2000",
+            "--bootstrap",
+            "/ip4/10.0.0.1/tcp/6969",
+            "--bootstrap",
+            "/ip4/10.0.0.2/tcp/6969",
+            "--allow",
+            &allowed.to_string(),
+        ]));
+        let defaults = Config::from_cli(parse(&[
+            "--config",
+            dir.join("missing.toml").to_str().unwrap(),
+        ]));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!(config.network, Network::Mainnet);
+        assert_eq!(config.listen, ["/ip4/127.0.0.1/tcp/2000".parse().unwrap()]);
+        assert_eq!(
+            config.bootstrap,
+            [
+                "/ip4/10.0.0.1/tcp/6969".parse().unwrap(),
+                "/ip4/10.0.0.2/tcp/6969".parse().unwrap()
+            ]
+        );
+        assert_eq!(config.allowlist, [listed, allowed]);
+        assert!(!config.tls);
+
+        let defaults = defaults.unwrap();
+        let expected = Config::default();
+        assert_eq!(defaults.network, expected.network);
+        assert_eq!(defaults.listen, expected.listen);
+        assert!(defaults.bootstrap.is_empty());
+        assert!(defaults.allowlist.is_empty());
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let dir = std::env::temp_dir().join(format!("tukecoin-config-bad-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CONFIG_FILE);
+        fs::write(&path, "listen_on = []\n").unwrap();
+
+        let result = Config::load(&path);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(result, Err(ConfigError::Parse(..))));
+    }
+}
@@ -0,0 +1,236 @@
+use std::fmt;
+
+/// Canonical binary encoding shared by hashing and the wire format.
+/// Integers are big-endian and fixed-width, variable-length fields carry a
+/// `u32` length prefix, and optional fields a one-byte presence tag, so no
+/// two different values encode to the same bytes.
+#[derive(Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn u8(&mut self, value: u8) -> &mut Self {
+        self.buf.push(value);
+        self
+    }
+
+    pub fn u32(&mut self, value: u32) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn u64(&mut self, value: u64) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn i64(&mut self, value: i64) -> &mut Self {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    /// Bytes whose length is implied by the type, e.g. a public key.
+    pub fn fixed(&mut self, value: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(value);
+        self
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.u32(value.len() as u32);
+        self.fixed(value)
+    }
+
+    pub fn str(&mut self, value: &str) -> &mut Self {
+        self.bytes(value.as_bytes())
+    }
+
+    pub fn option<T>(&mut self, value: Option<T>, f: impl FnOnce(&mut Self, T)) -> &mut Self {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                f(self, value);
+            }
+            None => {
+                self.u8(0);
+            }
+        }
+        self
+    }
+
+    pub fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buf)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DecodeError {
+    UnexpectedEnd,
+    InvalidUtf8,
+    InvalidTag(u8),
+    InvalidValue(&'static str),
+    UnsupportedVersion(u8),
+    TrailingBytes,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "input ended unexpectedly"),
+            DecodeError::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+            DecodeError::InvalidTag(tag) => write!(f, "invalid tag {}", tag),
+            DecodeError::InvalidValue(what) => write!(f, "invalid {}", what),
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported version {}", version)
+            }
+            DecodeError::TrailingBytes => write!(f, "unexpected bytes after the value"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+pub struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn fixed(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.data.len() < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (value, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(value)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.fixed(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_be_bytes(self.fixed(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, DecodeError> {
+        Ok(u64::from_be_bytes(self.fixed(8)?.try_into().unwrap()))
+    }
+
+    pub fn i64(&mut self) -> Result<i64, DecodeError> {
+        Ok(i64::from_be_bytes(self.fixed(8)?.try_into().unwrap()))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], DecodeError> {
+        let len = self.u32()? as usize;
+        self.fixed(len)
+    }
+
+    pub fn str(&mut self) -> Result<String, DecodeError> {
+        let bytes = self.bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+
+    pub fn option<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, DecodeError>,
+    ) -> Result<Option<T>, DecodeError> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => f(self).map(Some),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+
+    /// Fails unless the whole input has been consumed.
+    pub fn finish(&self) -> Result<(), DecodeError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(DecodeError::TrailingBytes)
+        }
+    }
+}
+
+pub trait Encode {
+    fn encode(&self, encoder: &mut Encoder);
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        self.encode(&mut encoder);
+        encoder.finish()
+    }
+}
+
+pub trait Decode: Sized {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError>;
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut decoder = Decoder::new(bytes);
+        let value = Self::decode(&mut decoder)?;
+        decoder.finish()?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primitives_round_trip() {
+        let bytes = Encoder::new()
+            .u8(7)
+            .u32(70_000)
+            .u64(u64::MAX)
+            .i64(-5)
+            .str("tuke")
+            .option(Some(3u8), |encoder, value| {
+                encoder.u8(value);
+            })
+            .option(None::<u8>, |encoder, value| {
+                encoder.u8(value);
+            })
+            .finish();
+
+        let mut decoder = Decoder::new(&bytes);
+        assert_eq!(decoder.u8(), Ok(7));
+        assert_eq!(decoder.u32(), Ok(70_000));
+        assert_eq!(decoder.u64(), Ok(u64::MAX));
+        assert_eq!(decoder.i64(), Ok(-5));
+        assert_eq!(decoder.str(), Ok("tuke".to_string()));
+        assert_eq!(decoder.option(Decoder::u8), Ok(Some(3)));
+        assert_eq!(decoder.option(Decoder::u8), Ok(None));
+        assert_eq!(decoder.finish(), Ok(()));
+    }
+
+    #[test]
+    fn length_prefix_separates_adjacent_strings() {
+        let ab_c = Encoder::new().str("ab").str("c").finish();
+        let a_bc = Encoder::new().str("a").str("bc").finish();
+        assert_ne!(ab_c, a_bc);
+    }
+
+    #[test]
+    fn truncated_and_malformed_input_is_rejected() {
+        let bytes = Encoder::new().str("tuke").finish();
+        let mut decoder = Decoder::new(&bytes[..bytes.len() - 1]);
+        assert_eq!(decoder.str(), Err(DecodeError::UnexpectedEnd));
+
+        assert_eq!(
+            Decoder::new(&[2]).option(Decoder::u8),
+            Err(DecodeError::InvalidTag(2))
+        );
+
+        let mut decoder = Decoder::new(&[1, 2]);
+        decoder.u8().unwrap();
+        assert_eq!(decoder.finish(), Err(DecodeError::TrailingBytes));
+    }
+}
@@ -0,0 +1,101 @@
+use sha2::{Digest, Sha256};
+
+use crate::address::Network;
+use crate::blockchain::Block;
+use crate::encoding::Encoder;
+use crate::ledger::{Ledger, LedgerError};
+use crate::pow::{meets_target, MAX_TARGET_BITS};
+
+/// Coins credited by the genesis block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Allocation {
+    pub address: &'static str,
+    pub amount: u64,
+}
+
+/// The root block every node of a network agrees on.
+///
+/// The genesis block has no transactions. Its merkle root commits to the
+/// allocations instead, so they cannot be changed without changing the hash.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Genesis {
+    pub timestamp: i64,
+    pub bits: u32,
+    pub nonce: u64,
+    pub hash: &'static str,
+    pub allocations: &'static [Allocation],
+}
+
+pub const MAINNET: Genesis = Genesis {
+    timestamp: 1_760_000_000,
+    bits: MAX_TARGET_BITS,
+    nonce: 193,
+    hash: "00bb758d7a1fac95b898494981c31aca0b1f41af73c33c5d82d0ded7fe73b433",
+    allocations: &[],
+};
+
+/// The well-known BIP39 test phrase. Anyone can restore its wallet.
+pub const TESTNET_FAUCET_PHRASE: &str = "abandon abandon abandon abandon abandon abandon \
+     abandon abandon abandon abandon abandon about";
+
+/// Testnet funds a faucet at the first receive address of
+/// `TESTNET_FAUCET_PHRASE`, so anyone can spend them.
+pub const TESTNET: Genesis = Genesis {
+    timestamp: 1_760_000_000,
+    bits: MAX_TARGET_BITS,
+    nonce: 102,
+    hash: "00b4a1c5a79771b4193f209b08c8233528fa84d728188a53fe6247f17be64905",
+    allocations: &[Allocation {
+        address: "4kWYoV72kz635GUJNDZuyPXmmwtArzfKKD59S7dp6veeeWndMBb",
+        amount: 1_000_000,
+    }],
+};
+
+impl Genesis {
+    pub fn for_network(network: Network) -> &'static Self {
+        match network {
+            Network::Mainnet => &MAINNET,
+            Network::Testnet => &TESTNET,
+        }
+    }
+
+    /// Hash of the allocations, stored as the block's merkle root.
+    pub fn allocations_root(&self) -> String {
+        let mut encoder = Encoder::new();
+        encoder.u32(self.allocations.len() as u32);
+        for allocation in self.allocations {
+            encoder.str(allocation.address).u64(allocation.amount);
+        }
+        hex::encode(Sha256::digest(encoder.finish()))
+    }
+
+    /// Builds the genesis block, panicking if it does not reproduce the
+    /// hard-coded hash; that would be a bug in the constants, not bad input.
+    pub fn block(&self) -> Block {
+        let mut block = Block::template(0, Vec::new(), "0".to_string(), self.timestamp, self.bits);
+        block.merkle_root = self.allocations_root();
+        block.nonce = self.nonce;
+
+        let hash = block.calculate_hash(self.nonce);
+        block.hash = hex::encode(hash);
+        assert_eq!(
+            block.hash, self.hash,
+            "hard-coded genesis does not match its hash"
+        );
+        assert!(
+            meets_target(&hash, self.bits),
+            "hard-coded genesis does not meet its target"
+        );
+
+        block
+    }
+
+    /// Ledger right after genesis, holding only the allocations.
+    pub fn ledger(&self) -> Result<Ledger, LedgerError> {
+        let mut ledger = Ledger::new();
+        for allocation in self.allocations {
+            ledger.allocate(allocation.address, allocation.amount)?;
+        }
+        Ok(ledger)
+    }
+}
@@ -0,0 +1,153 @@
+use hmac::{Hmac, Mac};
+use secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey};
+use sha2::Sha512;
+
+/// Child indices at or above this use hardened derivation.
+pub const HARDENED: u32 = 1 << 31;
+
+type HmacSha512 = Hmac<Sha512>;
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let mut mac = HmacSha512::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    let bytes = mac.finalize().into_bytes();
+
+    let mut left = [0u8; 32];
+    let mut right = [0u8; 32];
+    left.copy_from_slice(&bytes[..32]);
+    right.copy_from_slice(&bytes[32..]);
+    (left, right)
+}
+
+/// BIP32 extended private key: a secret key plus the chain code its
+/// children are derived with.
+#[derive(Debug, Clone)]
+pub struct ExtendedKey {
+    pub secret_key: SecretKey,
+    pub chain_code: [u8; 32],
+}
+
+impl ExtendedKey {
+    /// Master key of a BIP39 seed. `None` for the vanishingly rare seed that
+    /// does not give a valid key.
+    pub fn master(seed: &[u8]) -> Option<Self> {
+        let (key, chain_code) = hmac_sha512(b"Bitcoin seed", seed);
+
+        Some(Self {
+            secret_key: SecretKey::from_byte_array(key).ok()?,
+            chain_code,
+        })
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.secret_key.public_key(&Secp256k1::new())
+    }
+
+    /// Child `index`, hardened when `index >= HARDENED`. `None` when the
+    /// index gives an invalid key, in which case BIP32 says to skip it.
+    pub fn child(&self, index: u32) -> Option<Self> {
+        let mut data = Vec::with_capacity(37);
+        if index >= HARDENED {
+            data.push(0);
+            data.extend_from_slice(&self.secret_key.secret_bytes());
+        } else {
+            data.extend_from_slice(&self.public_key().serialize());
+        }
+        data.extend_from_slice(&index.to_be_bytes());
+
+        let (tweak, chain_code) = hmac_sha512(&self.chain_code, &data);
+        let tweak = Scalar::from_be_bytes(tweak).ok()?;
+
+        Some(Self {
+            secret_key: self.secret_key.add_tweak(&tweak).ok()?,
+            chain_code,
+        })
+    }
+
+    /// Follows `path` from this key, e.g. `[44 | HARDENED, 0]`.
+    pub fn derive(&self, path: &[u32]) -> Option<Self> {
+        path.iter()
+            .try_fold(self.clone(), |key, &index| key.child(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Private key and chain code at each step of a BIP32 test vector.
+    fn check(key: &ExtendedKey, secret_key: &str, chain_code: &str) {
+        assert_eq!(hex::encode(key.secret_key.secret_bytes()), secret_key);
+        assert_eq!(hex::encode(key.chain_code), chain_code);
+    }
+
+    #[test]
+    fn bip32_test_vector_1() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedKey::master(&seed).unwrap();
+        check(
+            &master,
+            "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35",
+            "873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508",
+        );
+
+        let steps = [
+            (
+                HARDENED,
+                "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea",
+                "47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141",
+            ),
+            (
+                1,
+                "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368",
+                "2a7857631386ba23dacac34180dd1983734e444fdbf774041578e9b6adb37c19",
+            ),
+            (
+                2 | HARDENED,
+                "cbce0d719ecf7431d88e6a89fa1483e02e35092af60c042b1df2ff59fa424dca",
+                "04466b9cc8e161e966409ca52986c584f07e9dc81f735db683c3ff6ec7b1503f",
+            ),
+            (
+                2,
+                "0f479245fb19a38a1954c5c7c0ebab2f9bdfd96a17563ef28a6a4b1a2a764ef4",
+                "cfb71883f01676f587d023cc53a35bc7f88f724b1f8c2892ac1275ac822a3edd",
+            ),
+            (
+                1_000_000_000,
+                "471b76e389e528d6de6d816857e012c5455051cad6660850e58372a6c3e6e7c8",
+                "c783e67b921d2beb8f6b389cc646d7263b4145701dadd2161548a8b078e65e9e",
+            ),
+        ];
+
+        let mut key = master;
+        for (index, secret_key, chain_code) in steps {
+            key = key.child(index).unwrap();
+            check(&key, secret_key, chain_code);
+        }
+
+        let path: Vec<u32> = steps.iter().map(|(index, _, _)| *index).collect();
+        let derived = ExtendedKey::master(&seed).unwrap().derive(&path).unwrap();
+        check(&derived, steps[4].1, steps[4].2);
+    }
+
+    #[test]
+    fn bip32_test_vector_3_keeps_leading_zeros() {
+        let seed = hex::decode(
+            "4b381541583be4423346c643850da4b320e46a87ae3d2a4e6da11eba819cd4ac\
+             ba45d239319ac14f863b8d5ab5a0d0c64d2e8a1e7d1457df2e5a3c51c73235be",
+        )
+        .unwrap();
+        let master = ExtendedKey::master(&seed).unwrap();
+        check(
+            &master,
+            "00ddb80b067e0d4993197fe10f2657a844a384589847602d56f0c629c81aae32",
+            "01d28a3e53cffa419ec122c968b3259e16b65076495494d97cae10bbfec3c36f",
+        );
+
+        check(
+            &master.child(HARDENED).unwrap(),
+            "491f7a2eebc7b57028e0d3faa0acda02e75c33b03c48fb288c41e2ea44e1daef",
+            "e5fea12a97b927fc9dc3d2cb0d1ea1cf50aa5a1fdc1f933e8906bb38df3377bd",
+        );
+    }
+}
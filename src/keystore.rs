@@ -0,0 +1,294 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use secp256k1::rand::{self, RngCore};
+
+use crate::encoding::{Decode, DecodeError, Decoder, Encode, Encoder};
+use crate::storage;
+
+const KEYSTORE_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+/// Ceilings on the stored KDF parameters. They are read before the file is
+/// authenticated, so a tampered file must not make unlocking allocate or
+/// compute without bound.
+const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const MAX_ITERATIONS: u32 = 64;
+const MAX_PARALLELISM: u32 = 16;
+
+#[derive(Debug)]
+pub enum KeystoreError {
+    Io(io::Error),
+    Decode(DecodeError),
+    UnsupportedVersion(u8),
+    Kdf(argon2::Error),
+    /// The passphrase is wrong or the file was tampered with; AEAD cannot
+    /// tell the two apart.
+    WrongPassphrase,
+    InvalidSecret,
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeystoreError::Io(e) => write!(f, "keystore i/o failed: {}", e),
+            KeystoreError::Decode(e) => write!(f, "keystore is corrupt: {}", e),
+            KeystoreError::UnsupportedVersion(version) => {
+                write!(f, "unsupported keystore version {}", version)
+            }
+            KeystoreError::Kdf(e) => write!(f, "key derivation failed: {}", e),
+            KeystoreError::WrongPassphrase => write!(f, "wrong passphrase"),
+            KeystoreError::InvalidSecret => write!(f, "keystore holds an invalid secret"),
+        }
+    }
+}
+
+impl std::error::Error for KeystoreError {}
+
+impl From<io::Error> for KeystoreError {
+    fn from(e: io::Error) -> Self {
+        KeystoreError::Io(e)
+    }
+}
+
+impl From<DecodeError> for KeystoreError {
+    fn from(e: DecodeError) -> Self {
+        KeystoreError::Decode(e)
+    }
+}
+
+impl From<argon2::Error> for KeystoreError {
+    fn from(e: argon2::Error) -> Self {
+        KeystoreError::Kdf(e)
+    }
+}
+
+/// Argon2id cost parameters, stored with the keystore so they can be raised
+/// later without breaking old files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    fn is_within_limits(&self) -> bool {
+        self.memory_kib <= MAX_MEMORY_KIB
+            && self.iterations <= MAX_ITERATIONS
+            && self.parallelism <= MAX_PARALLELISM
+    }
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// A secret encrypted under a passphrase.
+///
+/// The passphrase is stretched with Argon2id into a ChaCha20-Poly1305 key.
+/// The version, parameters and salt are authenticated as associated data, so
+/// none of them can be altered without failing decryption.
+#[derive(Debug, Clone)]
+pub struct Keystore {
+    params: KdfParams,
+    salt: [u8; SALT_LEN],
+    nonce: [u8; NONCE_LEN],
+    ciphertext: Vec<u8>,
+}
+
+impl Keystore {
+    pub fn seal(secret: &[u8], passphrase: &str) -> Result<Self, KeystoreError> {
+        Self::seal_with(secret, passphrase, KdfParams::default())
+    }
+
+    pub fn seal_with(
+        secret: &[u8],
+        passphrase: &str,
+        params: KdfParams,
+    ) -> Result<Self, KeystoreError> {
+        let mut rng = rand::rng();
+        let mut keystore = Self {
+            params,
+            salt: [0; SALT_LEN],
+            nonce: [0; NONCE_LEN],
+            ciphertext: Vec::new(),
+        };
+        rng.fill_bytes(&mut keystore.salt);
+        rng.fill_bytes(&mut keystore.nonce);
+
+        let cipher = keystore.cipher(passphrase)?;
+        let payload = Payload {
+            msg: secret,
+            aad: &keystore.header(),
+        };
+        keystore.ciphertext = cipher
+            .encrypt(Nonce::from_slice(&keystore.nonce), payload)
+            .map_err(|_| KeystoreError::InvalidSecret)?;
+
+        Ok(keystore)
+    }
+
+    /// Decrypts the secret, failing with `WrongPassphrase` on any mismatch.
+    pub fn open(&self, passphrase: &str) -> Result<Vec<u8>, KeystoreError> {
+        let cipher = self.cipher(passphrase)?;
+        let payload = Payload {
+            msg: &self.ciphertext,
+            aad: &self.header(),
+        };
+        cipher
+            .decrypt(Nonce::from_slice(&self.nonce), payload)
+            .map_err(|_| KeystoreError::WrongPassphrase)
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, KeystoreError> {
+        let data = fs::read(path)?;
+        match data.first() {
+            Some(&version) if version != KEYSTORE_VERSION => {
+                Err(KeystoreError::UnsupportedVersion(version))
+            }
+            _ => Ok(Self::from_bytes(&data)?),
+        }
+    }
+
+    /// Writes through a temporary file so a crash never leaves a torn keystore.
+    /// On unix only the owner may read it.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), KeystoreError> {
+        storage::write_private(path.as_ref(), &self.to_bytes())?;
+        Ok(())
+    }
+
+    fn cipher(&self, passphrase: &str) -> Result<ChaCha20Poly1305, KeystoreError> {
+        let params = Params::new(
+            self.params.memory_kib,
+            self.params.iterations,
+            self.params.parallelism,
+            Some(KEY_LEN),
+        )?;
+        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+
+        let mut key = [0u8; KEY_LEN];
+        argon2.hash_password_into(passphrase.as_bytes(), &self.salt, &mut key)?;
+
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+
+    /// Everything before the ciphertext.
+    fn header(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        self.encode_header(&mut encoder);
+        encoder.finish()
+    }
+
+    fn encode_header(&self, encoder: &mut Encoder) {
+        encoder
+            .u8(KEYSTORE_VERSION)
+            .u32(self.params.memory_kib)
+            .u32(self.params.iterations)
+            .u32(self.params.parallelism)
+            .fixed(&self.salt)
+            .fixed(&self.nonce);
+    }
+}
+
+impl Encode for Keystore {
+    fn encode(&self, encoder: &mut Encoder) {
+        self.encode_header(encoder);
+        encoder.bytes(&self.ciphertext);
+    }
+}
+
+impl Decode for Keystore {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        let version = decoder.u8()?;
+        if version != KEYSTORE_VERSION {
+            return Err(DecodeError::InvalidTag(version));
+        }
+
+        let params = KdfParams {
+            memory_kib: decoder.u32()?,
+            iterations: decoder.u32()?,
+            parallelism: decoder.u32()?,
+        };
+        if !params.is_within_limits() {
+            return Err(DecodeError::InvalidValue("key derivation parameters"));
+        }
+
+        Ok(Self {
+            params,
+            salt: decoder.fixed(SALT_LEN)?.try_into().unwrap(),
+            nonce: decoder.fixed(NONCE_LEN)?.try_into().unwrap(),
+            ciphertext: decoder.bytes()?.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap parameters so the tests do not spend seconds in Argon2.
+    const FAST: KdfParams = KdfParams {
+        memory_kib: 8,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn opens_with_the_right_passphrase_only() {
+        let keystore = Keystore::seal_with(b"secret", "hunter2", FAST).unwrap();
+        let keystore = Keystore::from_bytes(&keystore.to_bytes()).unwrap();
+
+        assert_eq!(keystore.open("hunter2").unwrap(), b"secret");
+        assert!(matches!(
+            keystore.open("hunter3"),
+            Err(KeystoreError::WrongPassphrase)
+        ));
+    }
+
+    #[test]
+    fn oversized_kdf_parameters_are_rejected() {
+        let mut bytes = Keystore::seal_with(b"secret", "hunter2", FAST)
+            .unwrap()
+            .to_bytes();
+        bytes[1..5].copy_from_slice(&u32::MAX.to_be_bytes());
+
+        assert_eq!(
+            Keystore::from_bytes(&bytes).unwrap_err(),
+            DecodeError::InvalidValue("key derivation parameters")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn written_keystore_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("tukecoin-keystore-{}", std::process::id()));
+        let path = dir.join("wallet.dat");
+        Keystore::seal_with(b"secret", "hunter2", FAST)
+            .unwrap()
+            .write(&path)
+            .unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        let read = Keystore::read(&path).map(|keystore| keystore.open("hunter2"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(read.unwrap().unwrap(), b"secret");
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::blockchain::{Block, Blockchain};
+use crate::genesis::Genesis;
+use crate::transaction::Transaction;
+
+#[derive(Debug, PartialEq)]
+pub enum LedgerError {
+    InvalidSignature,
+    UnexpectedCoinbase,
+    InvalidIndex {
+        address: String,
+        expected: u64,
+        found: u64,
+    },
+    Overspend {
+        address: String,
+        balance: u64,
+        amount: u64,
+    },
+    Overflow,
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerError::InvalidSignature => write!(f, "transaction signature is invalid"),
+            LedgerError::UnexpectedCoinbase => {
+                write!(
+                    f,
+                    "coinbase is only allowed as the first transaction of a block"
+                )
+            }
+            LedgerError::InvalidIndex {
+                address,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} sent transaction #{} but #{} was expected",
+                address, found, expected
+            ),
+            LedgerError::Overspend {
+                address,
+                balance,
+                amount,
+            } => write!(
+                f,
+                "{} tried to spend {} but only has {}",
+                address, amount, balance
+            ),
+            LedgerError::Overflow => write!(f, "balance overflow"),
+        }
+    }
+}
+
+impl std::error::Error for LedgerError {}
+
+/// Account balances obtained by replaying every transaction on the chain,
+/// along with the next transaction index each sender must use.
+#[derive(Debug, Default, Clone)]
+pub struct Ledger {
+    balances: HashMap<String, u64>,
+    next_indices: HashMap<String, u64>,
+    supply: u64,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_chain(blockchain: &Blockchain) -> Result<Self, LedgerError> {
+        let mut ledger = Genesis::for_network(blockchain.network).ledger()?;
+        for block in blockchain.chain.iter().skip(1) {
+            ledger.apply_block(block)?;
+        }
+        Ok(ledger)
+    }
+
+    pub fn balance(&self, address: &str) -> u64 {
+        self.balances.get(address).copied().unwrap_or(0)
+    }
+
+    /// Index the next transaction from `address` must carry. Each accepted
+    /// transaction bumps it, so a replayed or skipped index is rejected.
+    pub fn next_index(&self, address: &str) -> u64 {
+        self.next_indices.get(address).copied().unwrap_or(0)
+    }
+
+    /// Coins allocated at genesis and issued by coinbase transactions so far.
+    pub fn supply(&self) -> u64 {
+        self.supply
+    }
+
+    pub fn credit(&mut self, address: &str, amount: u64) -> Result<(), LedgerError> {
+        let balance = self.balances.entry(address.to_string()).or_insert(0);
+        *balance = balance.checked_add(amount).ok_or(LedgerError::Overflow)?;
+        Ok(())
+    }
+
+    /// Credits coins created outside any block, i.e. genesis allocations.
+    pub fn allocate(&mut self, address: &str, amount: u64) -> Result<(), LedgerError> {
+        self.credit(address, amount)?;
+        self.supply = self
+            .supply
+            .checked_add(amount)
+            .ok_or(LedgerError::Overflow)?;
+        Ok(())
+    }
+
+    fn debit(&mut self, address: &str, amount: u64) -> Result<(), LedgerError> {
+        let balance = self.balance(address);
+        if balance < amount {
+            return Err(LedgerError::Overspend {
+                address: address.to_string(),
+                balance,
+                amount,
+            });
+        }
+        self.balances.insert(address.to_string(), balance - amount);
+        Ok(())
+    }
+
+    pub fn apply_transaction(&mut self, tx: &Transaction) -> Result<(), LedgerError> {
+        if tx.is_coinbase() {
+            return Err(LedgerError::UnexpectedCoinbase);
+        }
+        if !tx.verify() {
+            return Err(LedgerError::InvalidSignature);
+        }
+
+        let expected = self.next_index(&tx.sender);
+        if tx.index != expected {
+            return Err(LedgerError::InvalidIndex {
+                address: tx.sender.clone(),
+                expected,
+                found: tx.index,
+            });
+        }
+
+        let cost = tx.total_cost().ok_or(LedgerError::Overflow)?;
+        self.debit(&tx.sender, cost)?;
+        self.credit(&tx.recipient, tx.amount)?;
+        self.next_indices.insert(tx.sender.clone(), expected + 1);
+        Ok(())
+    }
+
+    fn apply_coinbase(&mut self, tx: &Transaction) -> Result<(), LedgerError> {
+        if !tx.verify() {
+            return Err(LedgerError::InvalidSignature);
+        }
+
+        self.credit(&tx.recipient, tx.amount)?;
+        self.supply = self
+            .supply
+            .checked_add(tx.amount)
+            .ok_or(LedgerError::Overflow)?;
+        Ok(())
+    }
+
+    /// Applies all transactions of a block, or none of them if any is rejected.
+    pub fn apply_block(&mut self, block: &Block) -> Result<(), LedgerError> {
+        let mut staged = self.clone();
+        let mut transactions = block.transactions.iter().peekable();
+        let coinbase = transactions.next_if(|tx| tx.is_coinbase());
+
+        if let Some(coinbase) = coinbase {
+            staged.apply_coinbase(coinbase)?;
+        }
+
+        let mut fees: u64 = 0;
+        for tx in transactions {
+            staged.apply_transaction(tx)?;
+            fees = fees.checked_add(tx.fee).ok_or(LedgerError::Overflow)?;
+        }
+
+        // Fees go to the miner; a block without a coinbase burns them.
+        if let Some(coinbase) = coinbase {
+            staged.credit(&coinbase.recipient, fees)?;
+        }
+        *self = staged;
+        Ok(())
+    }
+
+    /// Undoes `apply_block` for the most recently applied block, used to roll
+    /// the ledger back to a fork point.
+    pub fn revert_block(&mut self, block: &Block) -> Result<(), LedgerError> {
+        let mut staged = self.clone();
+        let mut transactions = block.transactions.iter().peekable();
+        let coinbase = transactions.next_if(|tx| tx.is_coinbase());
+        let transactions: Vec<&Transaction> = transactions.collect();
+
+        if let Some(coinbase) = coinbase {
+            let fees = transactions
+                .iter()
+                .try_fold(0u64, |fees, tx| fees.checked_add(tx.fee))
+                .ok_or(LedgerError::Overflow)?;
+            staged.debit(&coinbase.recipient, fees)?;
+        }
+
+        for tx in transactions.iter().rev() {
+            let cost = tx.total_cost().ok_or(LedgerError::Overflow)?;
+            staged.debit(&tx.recipient, tx.amount)?;
+            staged.credit(&tx.sender, cost)?;
+            staged.next_indices.insert(tx.sender.clone(), tx.index);
+        }
+
+        if let Some(coinbase) = coinbase {
+            staged.debit(&coinbase.recipient, coinbase.amount)?;
+            staged.supply -= coinbase.amount;
+        }
+
+        *self = staged;
+        Ok(())
+    }
+}
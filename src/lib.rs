@@ -0,0 +1,17 @@
+pub mod address;
+pub mod blockchain;
+pub mod encoding;
+pub mod genesis;
+pub mod hd;
+pub mod keystore;
+pub mod ledger;
+pub mod mempool;
+pub mod merkle;
+pub mod miner;
+pub mod node;
+pub mod pow;
+pub mod protocol;
+pub mod storage;
+pub mod sync;
+pub mod transaction;
+pub mod wallet;
@@ -1,13 +1,23 @@
 mod app;
-mod network;
+mod config;
 
 use app::App;
+use clap::Parser;
+use config::{Cli, Config};
 
 #[tokio::main]
 async fn main() {
+    let config = match Config::from_cli(Cli::parse()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
     let terminal = ratatui::init();
 
-    let mut app: App = App::new(terminal);
+    let mut app: App = App::new(terminal, config);
     app.run();
 
     ratatui::restore();
@@ -0,0 +1,422 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::Utc;
+
+use crate::address::Network;
+use crate::blockchain::{by_fee_rate, Block};
+use crate::ledger::Ledger;
+use crate::transaction::Transaction;
+
+/// Maximum number of transactions kept in the pool.
+pub const MAX_MEMPOOL_SIZE: usize = 5_000;
+/// Seconds a transaction may wait for inclusion before it is dropped.
+pub const MEMPOOL_EXPIRY: i64 = 60 * 60 * 24;
+
+#[derive(Debug, PartialEq)]
+pub enum MempoolError {
+    InvalidSignature,
+    WrongNetwork,
+    Coinbase,
+    Duplicate,
+    InvalidIndex { expected: u64, found: u64 },
+    InsufficientFunds { balance: u64, required: u64 },
+    FeeTooLow,
+}
+
+impl fmt::Display for MempoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MempoolError::InvalidSignature => write!(f, "transaction signature is invalid"),
+            MempoolError::WrongNetwork => write!(f, "transaction is for another network"),
+            MempoolError::Coinbase => write!(f, "coinbase transactions are not relayed"),
+            MempoolError::Duplicate => write!(f, "transaction is already in the mempool"),
+            MempoolError::InvalidIndex { expected, found } => write!(
+                f,
+                "transaction #{} does not follow the sender's #{}",
+                found, expected
+            ),
+            MempoolError::InsufficientFunds { balance, required } => write!(
+                f,
+                "sender needs {} but only has {} after pending spends",
+                required, balance
+            ),
+            MempoolError::FeeTooLow => write!(f, "mempool is full and the fee is too low"),
+        }
+    }
+}
+
+impl std::error::Error for MempoolError {}
+
+struct Entry {
+    tx: Transaction,
+    added_at: i64,
+}
+
+/// Verified transactions of one network waiting to be mined, keyed by
+/// their hash.
+pub struct Mempool {
+    network: Network,
+    entries: HashMap<[u8; 32], Entry>,
+    max_size: usize,
+    expiry: i64,
+}
+
+impl Mempool {
+    pub fn new(network: Network) -> Self {
+        Self::with_limits(network, MAX_MEMPOOL_SIZE, MEMPOOL_EXPIRY)
+    }
+
+    pub fn with_limits(network: Network, max_size: usize, expiry: i64) -> Self {
+        Self {
+            network,
+            entries: HashMap::new(),
+            max_size,
+            expiry,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    pub fn get(&self, hash: &[u8; 32]) -> Option<&Transaction> {
+        self.entries.get(hash).map(|entry| &entry.tx)
+    }
+
+    pub fn transactions(&self) -> Vec<Transaction> {
+        self.entries
+            .values()
+            .map(|entry| entry.tx.clone())
+            .collect()
+    }
+
+    /// Index the sender's next pooled transaction must carry: one past
+    /// their highest pooled index, or the ledger's if they have none.
+    pub fn next_index(&self, sender: &str, ledger: &Ledger) -> u64 {
+        self.entries
+            .values()
+            .filter(|entry| entry.tx.sender == sender)
+            .map(|entry| entry.tx.index + 1)
+            .max()
+            .unwrap_or_else(|| ledger.next_index(sender))
+    }
+
+    /// Balance left to `address` once its pooled transactions are mined.
+    pub fn available_balance(&self, address: &str, ledger: &Ledger) -> u64 {
+        ledger
+            .balance(address)
+            .saturating_sub(self.pending_spend(address))
+    }
+
+    /// What `sender` would spend if every pooled transaction of theirs was mined.
+    fn pending_spend(&self, sender: &str) -> u64 {
+        self.entries
+            .values()
+            .filter(|entry| entry.tx.sender == sender)
+            .filter_map(|entry| entry.tx.total_cost())
+            .fold(0, u64::saturating_add)
+    }
+
+    /// Admits a transaction if it is signed, new, continues the sender's
+    /// index sequence and is affordable on top of their pending transactions.
+    /// A full pool makes room by evicting its lowest fee-rate entry, provided
+    /// the newcomer pays more.
+    pub fn add(&mut self, tx: Transaction, ledger: &Ledger) -> Result<[u8; 32], MempoolError> {
+        if tx.is_coinbase() {
+            return Err(MempoolError::Coinbase);
+        }
+        if !tx.verify() {
+            return Err(MempoolError::InvalidSignature);
+        }
+        if tx.network() != Some(self.network) {
+            return Err(MempoolError::WrongNetwork);
+        }
+
+        let hash = tx.hash_without_signature().unwrap_or_default();
+        if self.contains(&hash) {
+            return Err(MempoolError::Duplicate);
+        }
+
+        self.evict_expired(Utc::now().timestamp());
+
+        let expected = self.next_index(&tx.sender, ledger);
+        if tx.index != expected {
+            return Err(MempoolError::InvalidIndex {
+                expected,
+                found: tx.index,
+            });
+        }
+
+        let balance = ledger.balance(&tx.sender);
+        let required = tx
+            .total_cost()
+            .unwrap_or(u64::MAX)
+            .saturating_add(self.pending_spend(&tx.sender));
+        if balance < required {
+            return Err(MempoolError::InsufficientFunds { balance, required });
+        }
+
+        if self.entries.len() >= self.max_size {
+            // The sender's own entries are what the newcomer builds on.
+            let lowest = self
+                .entries
+                .values()
+                .filter(|entry| entry.tx.sender != tx.sender)
+                .max_by(|a, b| by_fee_rate(&a.tx, &b.tx))
+                .map(|entry| entry.tx.clone());
+
+            match lowest {
+                Some(lowest) if by_fee_rate(&tx, &lowest).is_lt() => {
+                    self.remove_from(&lowest.sender, lowest.index);
+                }
+                _ => return Err(MempoolError::FeeTooLow),
+            }
+        }
+
+        self.entries.insert(
+            hash,
+            Entry {
+                tx,
+                added_at: Utc::now().timestamp(),
+            },
+        );
+        Ok(hash)
+    }
+
+    /// Drops transactions that have waited longer than the expiry, along
+    /// with the later ones of the same sender that depend on them.
+    pub fn evict_expired(&mut self, now: i64) {
+        let expired: Vec<(String, u64)> = self
+            .entries
+            .values()
+            .filter(|entry| now.saturating_sub(entry.added_at) > self.expiry)
+            .map(|entry| (entry.tx.sender.clone(), entry.tx.index))
+            .collect();
+
+        for (sender, index) in expired {
+            self.remove_from(&sender, index);
+        }
+    }
+
+    /// Removes the sender's transaction `index` and every later one, so the
+    /// pool never holds a gap in their index sequence.
+    fn remove_from(&mut self, sender: &str, index: u64) {
+        self.entries
+            .retain(|_, entry| entry.tx.sender != sender || entry.tx.index < index);
+    }
+
+    /// Removes transactions included in `block`, then any left unaffordable
+    /// under the updated `ledger`.
+    pub fn remove_mined(&mut self, block: &Block, ledger: &Ledger) {
+        self.remove_included(block);
+        self.revalidate(ledger);
+    }
+
+    /// Follows a reorganization: transactions of the `disconnected` blocks
+    /// return to the pool, those of the `connected` ones leave it, and any
+    /// that the new `ledger` rejects are dropped.
+    pub fn reorganize(&mut self, disconnected: &[Block], connected: &[Block], ledger: &Ledger) {
+        let now = Utc::now().timestamp();
+        for tx in disconnected
+            .iter()
+            .flat_map(|block| &block.transactions)
+            .filter(|tx| !tx.is_coinbase())
+        {
+            if let Some(hash) = tx.hash_without_signature() {
+                self.entries.entry(hash).or_insert_with(|| Entry {
+                    tx: tx.clone(),
+                    added_at: now,
+                });
+            }
+        }
+
+        for block in connected {
+            self.remove_included(block);
+        }
+        self.revalidate(ledger);
+    }
+
+    fn remove_included(&mut self, block: &Block) {
+        for tx in &block.transactions {
+            if let Some(hash) = tx.hash_without_signature() {
+                self.entries.remove(&hash);
+            }
+        }
+    }
+
+    /// Keeps the transactions `ledger` accepts, tried in index order so each
+    /// sender's run stops at its first rejected one.
+    fn revalidate(&mut self, ledger: &Ledger) {
+        let mut remaining: Vec<([u8; 32], Entry)> = self.entries.drain().collect();
+        remaining.sort_by_key(|(_, entry)| entry.tx.index);
+
+        let mut staged = ledger.clone();
+        for (hash, entry) in remaining {
+            if staged.apply_transaction(&entry.tx).is_ok() {
+                self.entries.insert(hash, entry);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::genesis::{Genesis, TESTNET_FAUCET_PHRASE};
+    use crate::wallet::Wallet;
+
+    struct Sender {
+        wallet: Wallet,
+        address: String,
+    }
+
+    impl Sender {
+        fn new(wallet: Wallet, ledger: &mut Ledger) -> Self {
+            let address = wallet.address().to_string();
+            ledger.credit(&address, 1_000).unwrap();
+            Self { wallet, address }
+        }
+
+        /// Pays 10 coins back to the sender, signed by their first key.
+        fn pay(&self, index: u64, fee: u64) -> Transaction {
+            let key = &self.wallet.keys()[0];
+            let mut tx = Transaction::new(
+                index,
+                self.address.clone(),
+                self.address.clone(),
+                10,
+                fee,
+                None,
+                key.public_key,
+            );
+            tx.sign(&key.private_key);
+            tx
+        }
+    }
+
+    fn setup() -> (Ledger, Sender, Sender) {
+        let mut ledger = Genesis::for_network(Network::Testnet).ledger().unwrap();
+        let faucet = Wallet::from_phrase(TESTNET_FAUCET_PHRASE, Network::Testnet).unwrap();
+        let a = Sender::new(faucet, &mut ledger);
+        let b = Sender::new(Wallet::new(Network::Testnet), &mut ledger);
+        (ledger, a, b)
+    }
+
+    #[test]
+    fn next_index_follows_the_highest_pooled_index() {
+        let (ledger, a, _) = setup();
+        let mut pool = Mempool::new(Network::Testnet);
+        assert_eq!(pool.next_index(&a.address, &ledger), 0);
+
+        for index in 0..3 {
+            pool.add(a.pay(index, 1), &ledger).unwrap();
+        }
+        assert_eq!(pool.next_index(&a.address, &ledger), 3);
+        assert_eq!(
+            pool.add(a.pay(4, 1), &ledger),
+            Err(MempoolError::InvalidIndex {
+                expected: 3,
+                found: 4
+            })
+        );
+    }
+
+    #[test]
+    fn eviction_takes_the_senders_later_transactions_along() {
+        let (ledger, a, b) = setup();
+        let mut pool = Mempool::with_limits(Network::Testnet, 3, MEMPOOL_EXPIRY);
+
+        pool.add(a.pay(0, 5), &ledger).unwrap();
+        pool.add(a.pay(1, 1), &ledger).unwrap();
+        pool.add(a.pay(2, 5), &ledger).unwrap();
+        pool.add(b.pay(0, 10), &ledger).unwrap();
+
+        assert_eq!(pool.len(), 2);
+        assert_eq!(pool.next_index(&a.address, &ledger), 1);
+        pool.add(a.pay(1, 10), &ledger).unwrap();
+    }
+
+    #[test]
+    fn newcomer_does_not_evict_its_own_predecessors() {
+        let (ledger, a, _) = setup();
+        let mut pool = Mempool::with_limits(Network::Testnet, 2, MEMPOOL_EXPIRY);
+
+        pool.add(a.pay(0, 1), &ledger).unwrap();
+        pool.add(a.pay(1, 1), &ledger).unwrap();
+        assert_eq!(
+            pool.add(a.pay(2, 100), &ledger),
+            Err(MempoolError::FeeTooLow)
+        );
+        assert_eq!(pool.next_index(&a.address, &ledger), 2);
+    }
+
+    #[test]
+    fn expiry_takes_the_senders_later_transactions_along() {
+        let (ledger, a, _) = setup();
+        let mut pool = Mempool::new(Network::Testnet);
+        let first = pool.add(a.pay(0, 1), &ledger).unwrap();
+        pool.add(a.pay(1, 1), &ledger).unwrap();
+
+        pool.entries.get_mut(&first).unwrap().added_at -= MEMPOOL_EXPIRY + 1;
+        pool.evict_expired(Utc::now().timestamp());
+
+        assert!(pool.is_empty());
+        assert_eq!(pool.next_index(&a.address, &ledger), 0);
+    }
+
+    #[test]
+    fn add_evicts_expired_entries_before_checking_the_index() {
+        let (ledger, a, _) = setup();
+        let mut pool = Mempool::new(Network::Testnet);
+        let stale = pool.add(a.pay(0, 1), &ledger).unwrap();
+        pool.entries.get_mut(&stale).unwrap().added_at -= MEMPOOL_EXPIRY + 1;
+
+        pool.add(a.pay(0, 2), &ledger).unwrap();
+        assert!(!pool.contains(&stale));
+    }
+
+    #[test]
+    fn reorganize_returns_disconnected_transactions() {
+        let mut chain = Blockchain::new(Network::Testnet);
+        let genesis = chain.ledger().clone();
+        let (_, a, _) = setup();
+        let miner = Wallet::new(Network::Testnet);
+
+        let mined = a.pay(0, 1);
+        chain.add_block(vec![mined.clone()], &miner).unwrap();
+        let block = chain.tip().clone();
+
+        let mut pool = Mempool::new(Network::Testnet);
+        pool.add(a.pay(1, 1), chain.ledger()).unwrap();
+
+        pool.reorganize(std::slice::from_ref(&block), &[], &genesis);
+        assert_eq!(pool.len(), 2);
+        assert!(pool.contains(&mined.hash_without_signature().unwrap()));
+        assert_eq!(pool.next_index(&a.address, &genesis), 2);
+
+        pool.reorganize(&[], std::slice::from_ref(&block), chain.ledger());
+        assert_eq!(pool.len(), 1);
+        assert_eq!(pool.next_index(&a.address, chain.ledger()), 2);
+    }
+
+    #[test]
+    fn transaction_for_another_network_is_refused() {
+        let (ledger, a, _) = setup();
+        let mut pool = Mempool::new(Network::Mainnet);
+        assert_eq!(
+            pool.add(a.pay(0, 1), &ledger),
+            Err(MempoolError::WrongNetwork)
+        );
+        assert!(pool.is_empty());
+    }
+}
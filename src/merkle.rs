@@ -0,0 +1,168 @@
+use sha2::{Digest, Sha256};
+
+use crate::transaction::Transaction;
+
+pub type Hash = [u8; 32];
+
+fn hash_pair(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Binary hash tree over transaction hashes. An odd node at any level is
+/// paired with itself; a tree without leaves has an all-zero root.
+///
+/// Pairing means repeating the last leaves of an odd level gives the same
+/// root, so blocks must not contain the same transaction twice.
+pub struct MerkleTree {
+    levels: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn new(leaves: Vec<Hash>) -> Self {
+        let mut levels = vec![leaves];
+
+        while levels.last().unwrap().len() > 1 {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+                .collect();
+            levels.push(next);
+        }
+
+        Self { levels }
+    }
+
+    pub fn from_transactions(transactions: &[Transaction]) -> Self {
+        Self::new(transactions.iter().map(leaf_hash).collect())
+    }
+
+    pub fn root(&self) -> Hash {
+        self.levels
+            .last()
+            .and_then(|level| level.first())
+            .copied()
+            .unwrap_or([0; 32])
+    }
+
+    /// Sibling path from the leaf at `index` up to the root.
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.levels[0].len() {
+            return None;
+        }
+
+        let mut siblings = Vec::new();
+        let mut position = index;
+
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = level.get(position ^ 1).unwrap_or(&level[position]);
+            siblings.push(*sibling);
+            position /= 2;
+        }
+
+        Some(MerkleProof { index, siblings })
+    }
+}
+
+/// Leaf value a transaction contributes to the tree.
+pub fn leaf_hash(tx: &Transaction) -> Hash {
+    tx.hash_without_signature().unwrap_or_default()
+}
+
+#[derive(Debug, Clone)]
+pub struct MerkleProof {
+    pub index: usize,
+    pub siblings: Vec<Hash>,
+}
+
+impl MerkleProof {
+    /// Checks that `leaf` sits at `self.index` in a tree with the given root.
+    pub fn verify(&self, leaf: &Hash, root: &Hash) -> bool {
+        let depth = self.siblings.len() as u32;
+        if self.index.checked_shr(depth).unwrap_or(0) != 0 {
+            return false;
+        }
+
+        let mut hash = *leaf;
+        let mut position = self.index;
+
+        for sibling in &self.siblings {
+            hash = if position & 1 == 0 {
+                hash_pair(&hash, sibling)
+            } else {
+                hash_pair(sibling, &hash)
+            };
+            position /= 2;
+        }
+
+        &hash == root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(count: usize) -> Vec<Hash> {
+        (0..count).map(|i| [i as u8 + 1; 32]).collect()
+    }
+
+    #[test]
+    fn every_leaf_has_a_valid_proof() {
+        for count in 1..=9 {
+            let leaves = leaves(count);
+            let tree = MerkleTree::new(leaves.clone());
+            let root = tree.root();
+
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = tree.proof(index).unwrap();
+                assert!(proof.verify(leaf, &root), "leaf {} of {}", index, count);
+            }
+            assert!(tree.proof(count).is_none());
+        }
+    }
+
+    #[test]
+    fn proof_rejects_other_leaf_root_or_index() {
+        let leaves = leaves(5);
+        let tree = MerkleTree::new(leaves.clone());
+        let root = tree.root();
+        let proof = tree.proof(2).unwrap();
+
+        assert!(!proof.verify(&leaves[3], &root));
+        assert!(!proof.verify(&leaves[2], &[0; 32]));
+
+        let moved = MerkleProof {
+            index: 3,
+            ..proof.clone()
+        };
+        assert!(!moved.verify(&leaves[2], &root));
+
+        let out_of_range = MerkleProof {
+            index: proof.index + (1 << proof.siblings.len()),
+            ..proof
+        };
+        assert!(!out_of_range.verify(&leaves[2], &root));
+    }
+
+    #[test]
+    fn empty_tree_has_zero_root() {
+        assert_eq!(MerkleTree::new(Vec::new()).root(), [0; 32]);
+    }
+
+    #[test]
+    fn duplicated_last_leaf_keeps_the_root() {
+        let leaves = leaves(3);
+        let mut padded = leaves.clone();
+        padded.push(leaves[2]);
+
+        assert_eq!(
+            MerkleTree::new(leaves).root(),
+            MerkleTree::new(padded).root()
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::blockchain::Block;
+use crate::pow::meets_target;
+
+/// Nonces a worker tries between checks of the cancel flag.
+const BATCH_SIZE: u64 = 4096;
+
+#[derive(Debug, Clone, Copy)]
+pub struct MinerProgress {
+    pub hashes: u64,
+    pub elapsed: Duration,
+}
+
+impl MinerProgress {
+    pub fn hashrate(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.hashes as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Searches for a nonce on background threads, each covering its own slice
+/// of the nonce space. Mining stops when a block is found, when `cancel` is
+/// called (e.g. because a competing block arrived) or when the miner is
+/// dropped.
+pub struct Miner {
+    template: Block,
+    cancelled: Arc<AtomicBool>,
+    hashes: Arc<AtomicU64>,
+    started: Instant,
+    found: mpsc::Receiver<Block>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Miner {
+    pub fn start(template: Block, threads: usize) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let hashes = Arc::new(AtomicU64::new(0));
+        let (sender, found) = mpsc::channel();
+
+        let workers = nonce_ranges(threads)
+            .into_iter()
+            .map(|(start, end)| {
+                let block = template.clone();
+                let cancelled = cancelled.clone();
+                let hashes = hashes.clone();
+                let sender = sender.clone();
+
+                thread::spawn(move || {
+                    if let Some(block) = search(block, start, end, &cancelled, &hashes) {
+                        cancelled.store(true, Ordering::Relaxed);
+                        let _ = sender.send(block);
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            template,
+            cancelled,
+            hashes,
+            started: Instant::now(),
+            found,
+            workers,
+        }
+    }
+
+    /// Uses one worker per available CPU.
+    pub fn start_default(template: Block) -> Self {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::start(template, threads)
+    }
+
+    /// The block being mined, without a nonce.
+    pub fn template(&self) -> &Block {
+        &self.template
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether all workers have stopped, with or without a result.
+    pub fn is_finished(&self) -> bool {
+        self.workers.iter().all(JoinHandle::is_finished)
+    }
+
+    pub fn progress(&self) -> MinerProgress {
+        MinerProgress {
+            hashes: self.hashes.load(Ordering::Relaxed),
+            elapsed: self.started.elapsed(),
+        }
+    }
+
+    /// The mined block, if a worker has found one.
+    pub fn try_result(&self) -> Option<Block> {
+        self.found.try_recv().ok()
+    }
+
+    /// Blocks until a nonce is found or mining is cancelled.
+    pub fn wait(mut self) -> Option<Block> {
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        self.found.try_recv().ok()
+    }
+}
+
+impl Drop for Miner {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Splits the nonce space into one half-open range per worker. The ranges
+/// are adjacent and cover everything but `u64::MAX` itself.
+fn nonce_ranges(threads: usize) -> Vec<(u64, u64)> {
+    let threads = threads.max(1) as u64;
+    let span = u64::MAX / threads;
+    (0..threads)
+        .map(|i| {
+            let start = i * span;
+            let end = if i + 1 == threads {
+                u64::MAX
+            } else {
+                start + span
+            };
+            (start, end)
+        })
+        .collect()
+}
+
+fn search(
+    mut block: Block,
+    start: u64,
+    end: u64,
+    cancelled: &AtomicBool,
+    hashes: &AtomicU64,
+) -> Option<Block> {
+    let mut nonce = start;
+
+    while !cancelled.load(Ordering::Relaxed) {
+        let batch_end = end.min(nonce.saturating_add(BATCH_SIZE));
+
+        for candidate in nonce..batch_end {
+            let hash = block.calculate_hash(candidate);
+            if meets_target(&hash, block.bits) {
+                hashes.fetch_add(candidate - nonce + 1, Ordering::Relaxed);
+                block.nonce = candidate;
+                block.hash = hex::encode(hash);
+                return Some(block);
+            }
+        }
+
+        hashes.fetch_add(batch_end - nonce, Ordering::Relaxed);
+        if batch_end == end {
+            break;
+        }
+        nonce = batch_end;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Network;
+    use crate::blockchain::Blockchain;
+    use crate::pow::MAX_TARGET_BITS;
+    use crate::wallet::Wallet;
+
+    fn template(bits: u32) -> Block {
+        let chain = Blockchain::new(Network::Testnet);
+        let mut template = chain.block_template(Vec::new(), &Wallet::new(Network::Testnet));
+        template.bits = bits;
+        template
+    }
+
+    #[test]
+    fn found_block_meets_its_target() {
+        let miner = Miner::start(template(MAX_TARGET_BITS), 4);
+        let block = miner.wait().expect("the easiest target is found quickly");
+
+        let hash = block.calculate_hash(block.nonce);
+        assert_eq!(block.hash, hex::encode(hash));
+        assert!(meets_target(&hash, block.bits));
+    }
+
+    #[test]
+    fn cancel_stops_every_worker() {
+        // A target of 1 is never met in practice.
+        let miner = Miner::start(template(0x03000001), 4);
+        assert_eq!(miner.workers.len(), 4);
+        thread::sleep(Duration::from_millis(50));
+        assert!(!miner.is_finished());
+
+        miner.cancel();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !miner.is_finished() {
+            assert!(Instant::now() < deadline, "workers kept running");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(miner.try_result().is_none());
+        assert!(miner.wait().is_none());
+    }
+
+    #[test]
+    fn nonce_ranges_are_adjacent_and_disjoint() {
+        for threads in [0, 1, 2, 3, 7, 64] {
+            let ranges = nonce_ranges(threads);
+            assert_eq!(ranges.len(), threads.max(1));
+            assert_eq!(ranges[0].0, 0);
+            assert_eq!(ranges.last().unwrap().1, u64::MAX);
+
+            for (range, next) in ranges.iter().zip(&ranges[1..]) {
+                assert!(range.0 < range.1);
+                assert_eq!(range.1, next.0);
+            }
+        }
+    }
+}
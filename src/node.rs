@@ -0,0 +1,669 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use libp2p::{
+    allow_block_list::{self, AllowedPeers},
+    core::transport::TransportError,
+    floodsub::{Floodsub, FloodsubEvent, Topic},
+    futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, StreamExt},
+    identity::Keypair,
+    noise,
+    request_response::{self, ProtocolSupport, ResponseChannel},
+    swarm::{
+        behaviour::toggle::Toggle, Config, DialError, ListenError, NetworkBehaviour, SwarmEvent,
+    },
+    tcp, tls, yamux, Multiaddr, PeerId, StreamProtocol, Swarm, SwarmBuilder,
+};
+use tokio::sync::mpsc;
+
+use crate::encoding::{Decode, Encode};
+use crate::protocol::{
+    Message, BLOCKS_TOPIC, MAX_MESSAGE_SIZE, MAX_SYNC_MESSAGE_SIZE, SYNC_PROTOCOL, SYNC_TOPIC,
+    TRANSACTIONS_TOPIC,
+};
+use crate::storage;
+
+const MAX_IDLE: u64 = 60;
+const SYNC_TIMEOUT: u64 = 60;
+
+#[derive(Debug)]
+pub enum NodeError {
+    /// Noise or TLS could not be set up with the identity key.
+    Security(String),
+    Listen(Multiaddr, TransportError<io::Error>),
+}
+
+impl fmt::Display for NodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeError::Security(e) => write!(f, "could not set up encryption: {}", e),
+            NodeError::Listen(address, e) => write!(f, "could not listen on {}: {}", address, e),
+        }
+    }
+}
+
+impl std::error::Error for NodeError {}
+
+#[derive(Debug, Clone)]
+pub struct NodeConfig {
+    /// The node's ed25519 identity; its public key is the `PeerId`.
+    pub keypair: Keypair,
+    /// Addresses to accept connections on; `/tcp/0` picks a free port, which
+    /// is then reported with `NodeEvent::Listening`.
+    pub listen: Vec<Multiaddr>,
+    /// Peers dialed as soon as the node starts.
+    pub bootstrap: Vec<Multiaddr>,
+    /// Offer TLS 1.3 as well as Noise. Each connection uses whichever both
+    /// sides support, so nodes with and without TLS still talk.
+    pub tls: bool,
+    /// When not empty, connections to and from any other peer are refused.
+    pub allowlist: Vec<PeerId>,
+}
+
+impl NodeConfig {
+    /// Listens nowhere, dials no one and allows every peer.
+    pub fn new(keypair: Keypair) -> Self {
+        Self {
+            keypair,
+            listen: Vec::new(),
+            bootstrap: Vec::new(),
+            tls: false,
+            allowlist: Vec::new(),
+        }
+    }
+}
+
+/// Reads the identity key stored at `path`, generating and saving a new one
+/// on first use so the `PeerId` stays the same across restarts.
+pub fn load_or_create_identity(path: impl AsRef<Path>) -> io::Result<Keypair> {
+    let path = path.as_ref();
+    match fs::read(path) {
+        Ok(bytes) => Keypair::from_protobuf_encoding(&bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let keypair = Keypair::generate_ed25519();
+            let bytes = keypair
+                .to_protobuf_encoding()
+                .expect("ed25519 keys can be encoded");
+
+            storage::write_private(path, &bytes)?;
+
+            Ok(keypair)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// What the owner of a node asks of it, see `NodeHandle::send`.
+#[derive(Debug)]
+pub enum NodeCommand {
+    Dial(Multiaddr),
+    /// Floods a message on its topic.
+    Broadcast(Message),
+    /// Sends a sync request to one peer.
+    Request(PeerId, Message),
+    /// Answers the inbound sync request with this id, see
+    /// `NodeEvent::SyncRequest`. `None` refuses it.
+    Respond(u64, Option<Message>),
+    Disconnect(PeerId),
+    /// Closes every connection and ends `Node::run`.
+    Shutdown,
+}
+
+/// What a node reports back to its owner.
+#[derive(Debug, Clone)]
+pub enum NodeEvent {
+    Listening(Multiaddr),
+    /// A first connection to the peer, with the address it is reached at.
+    PeerConnected(PeerId, Multiaddr),
+    /// The last connection to the peer closed.
+    PeerDisconnected(PeerId),
+    /// A message flooded by the peer.
+    Message(PeerId, Message),
+    /// A sync request, to be answered with `NodeCommand::Respond`.
+    SyncRequest(PeerId, u64, Message),
+    SyncResponse(PeerId, Message),
+    SyncFailed(PeerId),
+    /// Anything else worth showing to a user.
+    Log(String),
+}
+
+/// Length-prefixed `Message`s over request-response streams.
+#[derive(Debug, Clone, Default)]
+pub struct SyncCodec;
+
+async fn read_message<T: AsyncRead + Unpin + Send>(io: &mut T) -> io::Result<Message> {
+    let mut len = [0u8; 4];
+    io.read_exact(&mut len).await?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_SYNC_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "sync message too large",
+        ));
+    }
+
+    let mut data = vec![0u8; len];
+    io.read_exact(&mut data).await?;
+    Message::from_bytes(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+async fn write_message<T: AsyncWrite + Unpin + Send>(
+    io: &mut T,
+    message: Message,
+) -> io::Result<()> {
+    let data = message.to_bytes();
+    io.write_all(&(data.len() as u32).to_be_bytes()).await?;
+    io.write_all(&data).await?;
+    io.close().await
+}
+
+#[async_trait]
+impl request_response::Codec for SyncCodec {
+    type Protocol = StreamProtocol;
+    type Request = Message;
+    type Response = Message;
+
+    async fn read_request<T>(&mut self, _: &StreamProtocol, io: &mut T) -> io::Result<Message>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_message(io).await
+    }
+
+    async fn read_response<T>(&mut self, _: &StreamProtocol, io: &mut T) -> io::Result<Message>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_message(io).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+        request: Message,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_message(io, request).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+        response: Message,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_message(io, response).await
+    }
+}
+
+#[derive(NetworkBehaviour)]
+pub struct Behaviour {
+    allowlist: Toggle<allow_block_list::Behaviour<AllowedPeers>>,
+    floodsub: Floodsub,
+    sync: request_response::Behaviour<SyncCodec>,
+}
+
+impl Behaviour {
+    fn new(keypair: &Keypair, allowlist: &[PeerId]) -> Self {
+        let allowlist = (!allowlist.is_empty()).then(|| {
+            let mut behaviour = allow_block_list::Behaviour::<AllowedPeers>::default();
+            for peer in allowlist {
+                behaviour.allow_peer(*peer);
+            }
+            behaviour
+        });
+
+        Self {
+            allowlist: Toggle::from(allowlist),
+            floodsub: Floodsub::new(keypair.public().to_peer_id()),
+            sync: request_response::Behaviour::new(
+                [(StreamProtocol::new(SYNC_PROTOCOL), ProtocolSupport::Full)],
+                request_response::Config::default()
+                    .with_request_timeout(Duration::from_secs(SYNC_TIMEOUT)),
+            ),
+        }
+    }
+}
+
+/// TCP connections encrypted and authenticated with Noise, or TLS when both
+/// sides offer it, and multiplexed with yamux.
+fn create_swarm(config: &NodeConfig) -> Result<Swarm<Behaviour>, NodeError> {
+    let builder = SwarmBuilder::with_existing_identity(config.keypair.clone()).with_tokio();
+    let behaviour = |keypair: &Keypair| Behaviour::new(keypair, &config.allowlist);
+    let swarm_config = |c: Config| c.with_idle_connection_timeout(Duration::from_secs(MAX_IDLE));
+
+    let swarm = if config.tls {
+        builder
+            .with_tcp(
+                tcp::Config::default(),
+                (tls::Config::new, noise::Config::new),
+                yamux::Config::default,
+            )
+            .map_err(|e| NodeError::Security(e.to_string()))?
+            .with_behaviour(behaviour)
+            .expect("creating the behaviour cannot fail")
+            .with_swarm_config(swarm_config)
+            .build()
+    } else {
+        builder
+            .with_tcp(
+                tcp::Config::default(),
+                noise::Config::new,
+                yamux::Config::default,
+            )
+            .map_err(|e| NodeError::Security(e.to_string()))?
+            .with_behaviour(behaviour)
+            .expect("creating the behaviour cannot fail")
+            .with_swarm_config(swarm_config)
+            .build()
+    };
+
+    Ok(swarm)
+}
+
+/// Sends commands to a running node. Dropping every handle shuts it down,
+/// just like `NodeCommand::Shutdown`.
+#[derive(Debug, Clone)]
+pub struct NodeHandle {
+    peer_id: PeerId,
+    commands: mpsc::UnboundedSender<NodeCommand>,
+}
+
+impl NodeHandle {
+    pub fn peer_id(&self) -> PeerId {
+        self.peer_id
+    }
+
+    /// Queues a command, returning false once the node has stopped.
+    pub fn send(&self, command: NodeCommand) -> bool {
+        self.commands.send(command).is_ok()
+    }
+}
+
+/// A peer of the TUKEcoin network: it listens, dials, floods messages on the
+/// protocol topics and serves sync requests, all driven from `run`.
+///
+/// ```ignore
+/// let (node, handle, mut events) = Node::new(&config)?;
+/// tokio::spawn(node.run());
+/// handle.send(NodeCommand::Dial(address));
+/// while let Some(event) = events.recv().await { ... }
+/// ```
+pub struct Node {
+    swarm: Swarm<Behaviour>,
+    commands: mpsc::UnboundedReceiver<NodeCommand>,
+    events: mpsc::UnboundedSender<NodeEvent>,
+    /// Inbound sync requests waiting to be answered.
+    pending: HashMap<u64, ResponseChannel<Message>>,
+    next_request_id: u64,
+}
+
+impl Node {
+    /// Starts listening and dialing the bootstrap peers; nothing else happens
+    /// until `run` is polled.
+    pub fn new(
+        config: &NodeConfig,
+    ) -> Result<(Self, NodeHandle, mpsc::UnboundedReceiver<NodeEvent>), NodeError> {
+        let (command_sender, commands) = mpsc::unbounded_channel();
+        let (events, event_receiver) = mpsc::unbounded_channel();
+
+        let mut node = Self {
+            swarm: create_swarm(config)?,
+            commands,
+            events,
+            pending: HashMap::new(),
+            next_request_id: 0,
+        };
+        let handle = NodeHandle {
+            peer_id: *node.swarm.local_peer_id(),
+            commands: command_sender,
+        };
+        node.log(format!("Peer id - {}", handle.peer_id));
+
+        for address in &config.listen {
+            node.swarm
+                .listen_on(address.clone())
+                .map_err(|e| NodeError::Listen(address.clone(), e))?;
+        }
+        for topic in [TRANSACTIONS_TOPIC, BLOCKS_TOPIC, SYNC_TOPIC] {
+            node.swarm
+                .behaviour_mut()
+                .floodsub
+                .subscribe(Topic::new(topic));
+        }
+        for address in &config.bootstrap {
+            node.dial(address.clone());
+        }
+
+        Ok((node, handle, event_receiver))
+    }
+
+    pub async fn run(mut self) {
+        loop {
+            tokio::select! {
+                Some(event) = self.swarm.next() => self.handle_swarm_event(event),
+                command = self.commands.recv() => match command {
+                    Some(NodeCommand::Shutdown) | None => break,
+                    Some(command) => self.handle_command(command),
+                },
+            }
+        }
+
+        let peers: Vec<PeerId> = self.swarm.connected_peers().copied().collect();
+        for peer in peers {
+            let _ = self.swarm.disconnect_peer_id(peer);
+        }
+        self.log("Node stopped".to_string());
+    }
+
+    fn emit(&self, event: NodeEvent) {
+        let _ = self.events.send(event);
+    }
+
+    fn log(&self, message: String) {
+        self.emit(NodeEvent::Log(message));
+    }
+
+    fn dial(&mut self, address: Multiaddr) {
+        match self.swarm.dial(address.clone()) {
+            Ok(()) => self.log(format!("Dialed {}", address)),
+            Err(e) => self.log(format!("⚠️ Could not dial {}: {}", address, e)),
+        }
+    }
+
+    fn publish(&mut self, message: Message) {
+        let data = message.to_bytes();
+        if data.len() > MAX_MESSAGE_SIZE {
+            self.log(format!(
+                "⚠️ {} is {} bytes, too large to publish",
+                message.name(),
+                data.len()
+            ));
+            return;
+        }
+
+        self.swarm
+            .behaviour_mut()
+            .floodsub
+            .publish(Topic::new(message.topic()), data);
+    }
+
+    fn handle_command(&mut self, command: NodeCommand) {
+        match command {
+            NodeCommand::Dial(address) => self.dial(address),
+            NodeCommand::Broadcast(message) => self.publish(message),
+            NodeCommand::Request(peer, message) => {
+                self.swarm.behaviour_mut().sync.send_request(&peer, message);
+            }
+            NodeCommand::Respond(id, message) => {
+                // Dropping the channel refuses the request.
+                if let (Some(channel), Some(message)) = (self.pending.remove(&id), message) {
+                    let _ = self
+                        .swarm
+                        .behaviour_mut()
+                        .sync
+                        .send_response(channel, message);
+                }
+            }
+            NodeCommand::Disconnect(peer) => {
+                let _ = self.swarm.disconnect_peer_id(peer);
+            }
+            NodeCommand::Shutdown => {}
+        }
+    }
+
+    fn handle_sync_event(&mut self, event: request_response::Event<Message, Message>) {
+        match event {
+            request_response::Event::Message { peer, message } => match message {
+                request_response::Message::Request {
+                    request, channel, ..
+                } => {
+                    let id = self.next_request_id;
+                    self.next_request_id += 1;
+                    self.pending.insert(id, channel);
+                    self.emit(NodeEvent::SyncRequest(peer, id, request));
+                }
+                request_response::Message::Response { response, .. } => {
+                    self.emit(NodeEvent::SyncResponse(peer, response));
+                }
+            },
+            request_response::Event::OutboundFailure { peer, error, .. } => {
+                self.log(format!("⚠️ Sync request to {:?} failed: {}", peer, error));
+                self.emit(NodeEvent::SyncFailed(peer));
+            }
+            request_response::Event::InboundFailure { peer, error, .. } => {
+                self.log(format!("⚠️ Sync request from {:?} failed: {}", peer, error));
+            }
+            request_response::Event::ResponseSent { .. } => {}
+        }
+    }
+
+    fn handle_swarm_event(&mut self, event: SwarmEvent<BehaviourEvent>) {
+        match event {
+            SwarmEvent::Behaviour(BehaviourEvent::Floodsub(FloodsubEvent::Message(m))) => {
+                match Message::from_bytes(&m.data) {
+                    Ok(message) => self.emit(NodeEvent::Message(m.source, message)),
+                    Err(e) => self.log(format!(
+                        "⚠️ Dropped malformed message from {:?}: {}",
+                        m.source, e
+                    )),
+                }
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Sync(event)) => self.handle_sync_event(event),
+            SwarmEvent::NewListenAddr { address, .. } => {
+                self.emit(NodeEvent::Listening(address));
+            }
+            SwarmEvent::ConnectionEstablished {
+                peer_id,
+                endpoint,
+                num_established,
+                ..
+            } => {
+                self.log(format!("✅ Connected to {:?}", peer_id));
+                self.swarm
+                    .behaviour_mut()
+                    .floodsub
+                    .add_node_to_partial_view(peer_id);
+                if num_established.get() == 1 {
+                    let address = endpoint.get_remote_address().clone();
+                    self.emit(NodeEvent::PeerConnected(peer_id, address));
+                }
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                num_established,
+                ..
+            } => {
+                self.log(format!("❌ Closed {:?}", peer_id));
+                if num_established == 0 {
+                    self.swarm
+                        .behaviour_mut()
+                        .floodsub
+                        .remove_node_from_partial_view(&peer_id);
+                    self.emit(NodeEvent::PeerDisconnected(peer_id));
+                }
+            }
+            // The allowlist is the only behaviour that denies connections.
+            SwarmEvent::IncomingConnectionError {
+                send_back_addr,
+                error: ListenError::Denied { .. },
+                ..
+            } => self.log(format!(
+                "⛔ Refused {}: peer is not on the allowlist",
+                send_back_addr
+            )),
+            SwarmEvent::OutgoingConnectionError {
+                peer_id,
+                error: DialError::Denied { .. },
+                ..
+            } => self.log(format!(
+                "⛔ Dropped {:?}: peer is not on the allowlist",
+                peer_id
+            )),
+            other => self.log(format!("🌀 Other: {:?}", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Inventory;
+    use tokio::time::{interval, timeout};
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn start() -> (
+        tokio::task::JoinHandle<()>,
+        NodeHandle,
+        mpsc::UnboundedReceiver<NodeEvent>,
+    ) {
+        start_with(Vec::new())
+    }
+
+    fn start_with(
+        allowlist: Vec<PeerId>,
+    ) -> (
+        tokio::task::JoinHandle<()>,
+        NodeHandle,
+        mpsc::UnboundedReceiver<NodeEvent>,
+    ) {
+        let mut config = NodeConfig::new(Keypair::generate_ed25519());
+        config.listen = vec!["/ip4/127.0.0.1/tcp/0".parse().unwrap()];
+        config.allowlist = allowlist;
+        let (node, handle, events) = Node::new(&config).unwrap();
+        (tokio::spawn(node.run()), handle, events)
+    }
+
+    /// Waits for the first event `f` picks out, skipping the others.
+    async fn expect<T>(
+        events: &mut mpsc::UnboundedReceiver<NodeEvent>,
+        mut f: impl FnMut(NodeEvent) -> Option<T>,
+    ) -> T {
+        timeout(TIMEOUT, async {
+            loop {
+                let event = events.recv().await.expect("node is running");
+                if let Some(value) = f(event) {
+                    return value;
+                }
+            }
+        })
+        .await
+        .expect("event arrives in time")
+    }
+
+    #[tokio::test]
+    async fn two_nodes_connect_flood_and_shut_down() {
+        let (a_task, a, mut a_events) = start();
+        let (b_task, b, mut b_events) = start();
+
+        let address = expect(&mut b_events, |event| match event {
+            NodeEvent::Listening(address) => Some(address),
+            _ => None,
+        })
+        .await;
+        assert!(a.send(NodeCommand::Dial(address)));
+
+        let peer = expect(&mut a_events, |event| match event {
+            NodeEvent::PeerConnected(peer, _) => Some(peer),
+            _ => None,
+        })
+        .await;
+        assert_eq!(peer, b.peer_id());
+
+        // Subscriptions are exchanged just after connecting, and floodsub
+        // only sends to peers known to be subscribed, so keep publishing.
+        let items = vec![Inventory::Transaction([7; 32])];
+        let (source, received) = timeout(TIMEOUT, async {
+            let mut retry = interval(Duration::from_millis(100));
+            loop {
+                tokio::select! {
+                    _ = retry.tick() => {
+                        a.send(NodeCommand::Broadcast(Message::Inv(items.clone())));
+                    }
+                    Some(event) = b_events.recv() => {
+                        if let NodeEvent::Message(source, Message::Inv(received)) = event {
+                            return (source, received);
+                        }
+                    }
+                }
+            }
+        })
+        .await
+        .expect("message arrives in time");
+        assert_eq!(source, a.peer_id());
+        assert_eq!(received, items);
+
+        assert!(a.send(NodeCommand::Shutdown));
+        timeout(TIMEOUT, a_task).await.unwrap().unwrap();
+        assert!(!a.send(NodeCommand::Shutdown));
+
+        let peer = expect(&mut b_events, |event| match event {
+            NodeEvent::PeerDisconnected(peer) => Some(peer),
+            _ => None,
+        })
+        .await;
+        assert_eq!(peer, a.peer_id());
+
+        drop(b);
+        timeout(TIMEOUT, b_task).await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn identity_survives_a_restart() {
+        let dir = std::env::temp_dir().join(format!("tukecoin-identity-{}", std::process::id()));
+        let path = dir.join("node.key");
+        let _ = fs::remove_dir_all(&dir);
+
+        let first = load_or_create_identity(&path).map(|keypair| keypair.public());
+        let second = load_or_create_identity(&path).map(|keypair| keypair.public());
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            fs::metadata(&path).unwrap().permissions().mode()
+        };
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(first.unwrap(), second.unwrap());
+        #[cfg(unix)]
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[tokio::test]
+    async fn allowlist_refuses_an_unlisted_peer() {
+        let (a_task, a, _a_events) = start();
+        let (b_task, b, mut b_events) = start_with(vec![PeerId::random()]);
+
+        let address = expect(&mut b_events, |event| match event {
+            NodeEvent::Listening(address) => Some(address),
+            _ => None,
+        })
+        .await;
+        assert!(a.send(NodeCommand::Dial(address)));
+
+        let refusal = expect(&mut b_events, |event| match event {
+            NodeEvent::PeerConnected(peer, _) => panic!("{} was let in", peer),
+            NodeEvent::Log(line) if line.contains("not on the allowlist") => Some(line),
+            _ => None,
+        })
+        .await;
+        assert!(refusal.contains("Refused"));
+
+        assert!(a.send(NodeCommand::Shutdown));
+        assert!(b.send(NodeCommand::Shutdown));
+        timeout(TIMEOUT, a_task).await.unwrap().unwrap();
+        timeout(TIMEOUT, b_task).await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,178 @@
+use primitive_types::U256;
+
+/// Easiest target any block may have, in compact form.
+pub const MAX_TARGET_BITS: u32 = 0x2000ffff;
+
+/// Expands a compact target: the high byte is a base-256 exponent and the
+/// low three bytes the mantissa, so `target = mantissa * 256^(exponent - 3)`.
+/// Like Bitcoin's nBits, a set `0x00800000` bit marks a negative, i.e.
+/// unusable, target and yields zero.
+pub fn bits_to_target(bits: u32) -> U256 {
+    let exponent = bits >> 24;
+    let mantissa = bits & 0x007f_ffff;
+
+    if bits & 0x0080_0000 != 0 || mantissa == 0 {
+        return U256::zero();
+    }
+
+    if exponent <= 3 {
+        U256::from(mantissa >> (8 * (3 - exponent)))
+    } else if exponent > 32 {
+        U256::MAX
+    } else {
+        let target = U256::from(mantissa);
+        let shift = 8 * (exponent - 3) as usize;
+        if target.leading_zeros() < shift as u32 {
+            U256::MAX
+        } else {
+            target << shift
+        }
+    }
+}
+
+/// Compresses a target, rounding it down to the 3 most significant bytes.
+pub fn target_to_bits(target: U256) -> u32 {
+    let mut exponent = target.bits().div_ceil(8) as u32;
+    let mut mantissa = if exponent <= 3 {
+        target.low_u32() << (8 * (3 - exponent))
+    } else {
+        (target >> (8 * (exponent - 3) as usize)).low_u32()
+    };
+
+    // Keep the sign bit clear by moving a byte into the exponent.
+    if mantissa & 0x0080_0000 != 0 {
+        mantissa >>= 8;
+        exponent += 1;
+    }
+
+    (exponent << 24) | mantissa
+}
+
+pub fn max_target() -> U256 {
+    bits_to_target(MAX_TARGET_BITS)
+}
+
+/// Whether `hash`, read as a big-endian number, is at or below the target.
+pub fn meets_target(hash: &[u8; 32], bits: u32) -> bool {
+    U256::from_big_endian(hash) <= bits_to_target(bits)
+}
+
+/// Expected number of hashes needed to find a block at this target,
+/// `2^256 / (target + 1)`.
+pub fn block_work(bits: u32) -> U256 {
+    let target = bits_to_target(bits);
+    if target == U256::MAX {
+        return U256::one();
+    }
+    (!target / (target + 1)) + 1
+}
+
+/// How many times harder than the easiest target this one is.
+pub fn difficulty(bits: u32) -> f64 {
+    let target = bits_to_target(bits);
+    if target.is_zero() {
+        return f64::INFINITY;
+    }
+    u256_to_f64(max_target()) / u256_to_f64(target)
+}
+
+fn u256_to_f64(value: U256) -> f64 {
+    value
+        .0
+        .iter()
+        .rev()
+        .fold(0.0, |acc, &limb| acc * 2f64.powi(64) + limb as f64)
+}
+
+/// Scales the target by how long the last interval took compared to how long
+/// it should have taken. The ratio is clamped to 4x either way, and the
+/// result never exceeds `MAX_TARGET_BITS`.
+pub fn retarget(bits: u32, actual: i64, expected: i64) -> u32 {
+    let expected = expected.max(1) as u64;
+    let actual = (actual.max(0) as u64).clamp(expected / 4, expected * 4);
+
+    let target = bits_to_target(bits);
+    let scaled = match target.checked_mul(U256::from(actual)) {
+        Some(product) => product / U256::from(expected),
+        None => U256::MAX,
+    };
+
+    target_to_bits(scaled.min(max_target()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalized_bits_round_trip() {
+        for bits in [
+            MAX_TARGET_BITS,
+            0x1d00ffff,
+            0x1b0404cb,
+            0x1f123456,
+            0x04123456,
+            0x03123456,
+            0x02008000,
+            0x01120000,
+        ] {
+            assert_eq!(target_to_bits(bits_to_target(bits)), bits, "{:#010x}", bits);
+        }
+    }
+
+    #[test]
+    fn compression_keeps_three_significant_bytes() {
+        let target = U256::from_big_endian(&[0x12, 0x34, 0x56, 0x78, 0x9a]);
+        let bits = target_to_bits(target);
+
+        assert_eq!(bits, 0x05123456);
+        assert_eq!(
+            bits_to_target(bits),
+            U256::from_big_endian(&[0x12, 0x34, 0x56, 0, 0])
+        );
+    }
+
+    #[test]
+    fn high_mantissa_bit_moves_into_the_exponent() {
+        assert_eq!(target_to_bits(U256::from(0x80)), 0x02008000);
+        assert_eq!(target_to_bits(U256::from(0x80) << 240), 0x20008000);
+    }
+
+    #[test]
+    fn negative_or_empty_targets_are_zero() {
+        assert!(bits_to_target(0x1d800000).is_zero());
+        assert!(bits_to_target(0x1d000000).is_zero());
+
+        let mut hash = [0u8; 32];
+        hash[31] = 1;
+        assert!(!meets_target(&hash, 0x1d800000));
+    }
+
+    #[test]
+    fn meets_target_compares_the_hash_as_a_number() {
+        let mut hash = [0u8; 32];
+        hash[1] = 0xff;
+        hash[2] = 0xff;
+        assert!(meets_target(&hash, MAX_TARGET_BITS));
+
+        hash[0] = 1;
+        assert!(!meets_target(&hash, MAX_TARGET_BITS));
+    }
+
+    #[test]
+    fn retarget_is_clamped_and_capped() {
+        let bits = 0x1f00ffff;
+        let target = bits_to_target(bits);
+
+        assert_eq!(retarget(bits, 300, 300), bits);
+        assert_eq!(bits_to_target(retarget(bits, 1, 300)), target / 4);
+        assert_eq!(bits_to_target(retarget(bits, 600, 300)), target * 2);
+        assert_eq!(retarget(MAX_TARGET_BITS, 10_000, 300), MAX_TARGET_BITS);
+    }
+
+    #[test]
+    fn harder_targets_are_more_work() {
+        assert!(block_work(0x1f00ffff) > block_work(MAX_TARGET_BITS));
+        assert_eq!(difficulty(MAX_TARGET_BITS), 1.0);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::blockchain::{Block, BlockHeader};
+use crate::encoding::{Decode, DecodeError, Decoder, Encode, Encoder};
+use crate::transaction::Transaction;
+
+/// Leading byte of every message. Peers drop messages of other versions.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+pub const TRANSACTIONS_TOPIC: &str = "tukecoin/transactions/1";
+pub const BLOCKS_TOPIC: &str = "tukecoin/blocks/1";
+pub const SYNC_TOPIC: &str = "tukecoin/sync/1";
+
+/// Request-response protocol used to download the chain from one peer.
+pub const SYNC_PROTOCOL: &str = "/tukecoin/sync/1";
+/// Upper bound on a sync request or response, in bytes.
+pub const MAX_SYNC_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Largest message worth publishing. Floodsub drops frames over 2 KiB, and
+/// the frame carries some overhead of its own.
+pub const MAX_MESSAGE_SIZE: usize = 1900;
+/// Most blocks or headers answered to a single request.
+pub const MAX_BLOCKS_PER_MESSAGE: usize = 500;
+
+/// Something a peer can announce by hash and fetch with `GetData`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inventory {
+    Transaction([u8; 32]),
+    Block(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// A transaction for the mempool.
+    NewTransaction(Box<Transaction>),
+    /// A freshly mined block.
+    NewBlock(Box<Block>),
+    /// Announces items too large to flood, or that a peer asked about.
+    Inv(Vec<Inventory>),
+    /// Asks for the full items behind an `Inv`: transactions when flooded,
+    /// blocks when sent as a sync request.
+    GetData(Vec<Inventory>),
+    /// Asks for the blocks after the fork point with `locator`, see
+    /// `Blockchain::locator`.
+    GetBlocks {
+        locator: Vec<String>,
+    },
+    Blocks(Vec<Block>),
+    /// Like `GetBlocks`, but only for headers.
+    GetHeaders {
+        locator: Vec<String>,
+    },
+    Headers(Vec<BlockHeader>),
+}
+
+impl Message {
+    /// Floodsub topic the message is published on.
+    pub fn topic(&self) -> &'static str {
+        match self {
+            Message::NewTransaction(_) => TRANSACTIONS_TOPIC,
+            Message::NewBlock(_) => BLOCKS_TOPIC,
+            Message::Inv(_)
+            | Message::GetData(_)
+            | Message::GetBlocks { .. }
+            | Message::Blocks(_)
+            | Message::GetHeaders { .. }
+            | Message::Headers(_) => SYNC_TOPIC,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Message::NewTransaction(_) => "NewTransaction",
+            Message::NewBlock(_) => "NewBlock",
+            Message::Inv(_) => "Inv",
+            Message::GetData(_) => "GetData",
+            Message::GetBlocks { .. } => "GetBlocks",
+            Message::Blocks(_) => "Blocks",
+            Message::GetHeaders { .. } => "GetHeaders",
+            Message::Headers(_) => "Headers",
+        }
+    }
+
+    fn tag(&self) -> u8 {
+        match self {
+            Message::NewTransaction(_) => 0,
+            Message::NewBlock(_) => 1,
+            Message::Inv(_) => 2,
+            Message::GetData(_) => 3,
+            Message::GetBlocks { .. } => 4,
+            Message::Blocks(_) => 5,
+            Message::GetHeaders { .. } => 6,
+            Message::Headers(_) => 7,
+        }
+    }
+}
+
+fn encode_list<T>(encoder: &mut Encoder, items: &[T], mut f: impl FnMut(&mut Encoder, &T)) {
+    encoder.u32(items.len() as u32);
+    for item in items {
+        f(encoder, item);
+    }
+}
+
+fn decode_list<'a, T>(
+    decoder: &mut Decoder<'a>,
+    mut f: impl FnMut(&mut Decoder<'a>) -> Result<T, DecodeError>,
+) -> Result<Vec<T>, DecodeError> {
+    let count = decoder.u32()?;
+    (0..count).map(|_| f(decoder)).collect()
+}
+
+impl Encode for Inventory {
+    fn encode(&self, encoder: &mut Encoder) {
+        match self {
+            Inventory::Transaction(hash) => {
+                encoder.u8(0).fixed(hash);
+            }
+            Inventory::Block(hash) => {
+                encoder.u8(1).str(hash);
+            }
+        }
+    }
+}
+
+impl Decode for Inventory {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        match decoder.u8()? {
+            0 => Ok(Inventory::Transaction(
+                decoder.fixed(32)?.try_into().unwrap(),
+            )),
+            1 => Ok(Inventory::Block(decoder.str()?)),
+            tag => Err(DecodeError::InvalidTag(tag)),
+        }
+    }
+}
+
+/// The protocol version, a tag naming the variant, then its fields.
+impl Encode for Message {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.u8(PROTOCOL_VERSION).u8(self.tag());
+
+        match self {
+            Message::NewTransaction(tx) => tx.encode(encoder),
+            Message::NewBlock(block) => block.encode(encoder),
+            Message::Inv(items) | Message::GetData(items) => {
+                encode_list(encoder, items, |encoder, item| item.encode(encoder));
+            }
+            Message::GetBlocks { locator } | Message::GetHeaders { locator } => {
+                encode_list(encoder, locator, |encoder, hash| {
+                    encoder.str(hash);
+                });
+            }
+            Message::Blocks(blocks) => {
+                encode_list(encoder, blocks, |encoder, block| block.encode(encoder));
+            }
+            Message::Headers(headers) => {
+                encode_list(encoder, headers, |encoder, header| header.encode(encoder));
+            }
+        }
+    }
+}
+
+impl Decode for Message {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        let version = decoder.u8()?;
+        if version != PROTOCOL_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        Ok(match decoder.u8()? {
+            0 => Message::NewTransaction(Box::new(Transaction::decode(decoder)?)),
+            1 => Message::NewBlock(Box::new(Block::decode(decoder)?)),
+            2 => Message::Inv(decode_list(decoder, Inventory::decode)?),
+            3 => Message::GetData(decode_list(decoder, Inventory::decode)?),
+            4 => Message::GetBlocks {
+                locator: decode_list(decoder, Decoder::str)?,
+            },
+            5 => Message::Blocks(decode_list(decoder, Block::decode)?),
+            6 => Message::GetHeaders {
+                locator: decode_list(decoder, Decoder::str)?,
+            },
+            7 => Message::Headers(decode_list(decoder, BlockHeader::decode)?),
+            tag => return Err(DecodeError::InvalidTag(tag)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Network;
+    use crate::genesis::Genesis;
+    use crate::wallet::Wallet;
+
+    fn messages() -> Vec<Message> {
+        let mut wallet = Wallet::new(Network::Testnet);
+        let from = wallet.address().to_string();
+        let tx = wallet
+            .create_transaction(&from, from.clone(), 1, 0, None)
+            .unwrap();
+        let block = Genesis::for_network(Network::Testnet).block();
+        let locator = vec![block.hash.clone(), "00ff".to_string()];
+        let items = vec![
+            Inventory::Transaction([7; 32]),
+            Inventory::Block(block.hash.clone()),
+        ];
+
+        vec![
+            Message::NewTransaction(Box::new(tx)),
+            Message::NewBlock(Box::new(block.clone())),
+            Message::Inv(items.clone()),
+            Message::GetData(items),
+            Message::GetBlocks {
+                locator: locator.clone(),
+            },
+            Message::Blocks(vec![block.clone(), block.clone()]),
+            Message::GetHeaders { locator },
+            Message::Headers(vec![block.header()]),
+        ]
+    }
+
+    #[test]
+    fn every_message_round_trips() {
+        for message in messages() {
+            let bytes = message.to_bytes();
+            let decoded = Message::from_bytes(&bytes).unwrap();
+
+            assert_eq!(decoded.name(), message.name());
+            assert_eq!(decoded.to_bytes(), bytes);
+        }
+    }
+
+    #[test]
+    fn truncated_padded_or_foreign_messages_are_rejected() {
+        for message in messages() {
+            let bytes = message.to_bytes();
+            assert!(Message::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+            let mut padded = bytes.clone();
+            padded.push(0);
+            assert_eq!(
+                Message::from_bytes(&padded).unwrap_err(),
+                DecodeError::TrailingBytes
+            );
+
+            let mut foreign = bytes;
+            foreign[0] = PROTOCOL_VERSION + 1;
+            assert_eq!(
+                Message::from_bytes(&foreign).unwrap_err(),
+                DecodeError::UnsupportedVersion(PROTOCOL_VERSION + 1)
+            );
+        }
+    }
+}
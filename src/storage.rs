@@ -0,0 +1,397 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::blockchain::Block;
+use crate::encoding::{Decode, Encode};
+
+const BLOCKS_FILE: &str = "blocks.dat";
+const INDEX_FILE: &str = "blocks.idx";
+
+/// What `BlockStore::repair` had to fix.
+#[derive(Debug, Default, PartialEq)]
+pub struct RepairReport {
+    /// Bytes cut from the end of the block file.
+    pub truncated_bytes: u64,
+    pub index_rebuilt: bool,
+}
+
+impl RepairReport {
+    pub fn is_clean(&self) -> bool {
+        self.truncated_bytes == 0 && !self.index_rebuilt
+    }
+}
+
+/// Append-only block file plus an index of record offsets.
+///
+/// Each record in the block file is a `u32` big-endian length followed by the
+/// encoded block. The index holds one `u64` big-endian offset per block, so
+/// block `n` starts at the `n`-th offset.
+pub struct BlockStore {
+    blocks: File,
+    index: File,
+    offsets: Vec<u64>,
+}
+
+impl BlockStore {
+    /// Opens (or creates) the store in `dir` and repairs any damage left by
+    /// an unclean shutdown.
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<(Self, RepairReport)> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        let open = |name| {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(dir.join(name))
+        };
+
+        let mut store = Self {
+            blocks: open(BLOCKS_FILE)?,
+            index: open(INDEX_FILE)?,
+            offsets: Vec::new(),
+        };
+        let report = store.repair()?;
+        Ok((store, report))
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Scans the block file, cuts off a torn or undecodable tail and rewrites
+    /// the index if it does not match the records that remain.
+    pub fn repair(&mut self) -> io::Result<RepairReport> {
+        let mut data = Vec::new();
+        self.blocks.seek(SeekFrom::Start(0))?;
+        self.blocks.read_to_end(&mut data)?;
+
+        let mut offsets = Vec::new();
+        let mut position = 0;
+        while let Some(len) = record_len(&data[position..]) {
+            let end = position + 4 + len;
+            if Block::from_bytes(&data[position + 4..end]).is_err() {
+                break;
+            }
+            offsets.push(position as u64);
+            position = end;
+        }
+
+        let mut report = RepairReport::default();
+
+        if position < data.len() {
+            report.truncated_bytes = (data.len() - position) as u64;
+            self.blocks.set_len(position as u64)?;
+            self.blocks.sync_data()?;
+        }
+
+        if self.read_index()? != offsets {
+            report.index_rebuilt = true;
+            self.write_index(&offsets)?;
+        }
+
+        self.offsets = offsets;
+        Ok(report)
+    }
+
+    fn read_index(&mut self) -> io::Result<Vec<u64>> {
+        let mut data = Vec::new();
+        self.index.seek(SeekFrom::Start(0))?;
+        self.index.read_to_end(&mut data)?;
+
+        if data.len() % 8 != 0 {
+            return Ok(Vec::new());
+        }
+        Ok(data
+            .chunks_exact(8)
+            .map(|chunk| u64::from_be_bytes(chunk.try_into().unwrap()))
+            .collect())
+    }
+
+    fn write_index(&mut self, offsets: &[u64]) -> io::Result<()> {
+        let data: Vec<u8> = offsets.iter().flat_map(|o| o.to_be_bytes()).collect();
+        self.index.set_len(0)?;
+        self.index.seek(SeekFrom::Start(0))?;
+        self.index.write_all(&data)?;
+        self.index.sync_data()
+    }
+
+    pub fn read_block(&mut self, height: usize) -> io::Result<Block> {
+        let Some(&offset) = self.offsets.get(height) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no block at height {}", height),
+            ));
+        };
+
+        let mut len = [0u8; 4];
+        self.blocks.seek(SeekFrom::Start(offset))?;
+        self.blocks.read_exact(&mut len)?;
+
+        let mut data = vec![0u8; u32::from_be_bytes(len) as usize];
+        self.blocks.read_exact(&mut data)?;
+
+        Block::from_bytes(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn load_blocks(&mut self) -> io::Result<Vec<Block>> {
+        (0..self.len())
+            .map(|height| self.read_block(height))
+            .collect()
+    }
+
+    /// Appends a block. The record is synced before the index entry is
+    /// written, so a crash in between only leaves a stale index to rebuild.
+    pub fn append(&mut self, block: &Block) -> io::Result<()> {
+        let data = block.to_bytes();
+        let offset = self.blocks.seek(SeekFrom::End(0))?;
+
+        self.blocks.write_all(&(data.len() as u32).to_be_bytes())?;
+        self.blocks.write_all(&data)?;
+        self.blocks.sync_data()?;
+
+        self.index.seek(SeekFrom::End(0))?;
+        self.index.write_all(&offset.to_be_bytes())?;
+        self.index.sync_data()?;
+
+        self.offsets.push(offset);
+        Ok(())
+    }
+
+    /// Drops every block from height `len` on, e.g. when the best chain
+    /// switches to another branch.
+    pub fn truncate(&mut self, len: usize) -> io::Result<()> {
+        let Some(&offset) = self.offsets.get(len) else {
+            return Ok(());
+        };
+
+        self.blocks.set_len(offset)?;
+        self.blocks.sync_data()?;
+        self.index.set_len(len as u64 * 8)?;
+        self.index.sync_data()?;
+
+        self.offsets.truncate(len);
+        Ok(())
+    }
+}
+
+/// Length of the record at the start of `data`, if it is complete.
+/// Atomically replaces `path` with `data`, readable by the owner only.
+///
+/// The file is created private rather than narrowed afterwards, so the
+/// secret is never visible to others, and it is synced before the rename
+/// so a crash cannot leave an empty file in its place.
+pub(crate) fn write_private(path: &Path, data: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let tmp = path.with_extension("tmp");
+    match fs::remove_file(&tmp) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp, path)
+}
+
+fn record_len(data: &[u8]) -> Option<usize> {
+    let len = u32::from_be_bytes(data.get(..4)?.try_into().unwrap()) as usize;
+    (data.len() >= 4 + len).then_some(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Network;
+    use crate::blockchain::Blockchain;
+    use crate::wallet::Wallet;
+    use std::path::PathBuf;
+
+    /// Fresh directory for one test, removed again when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "tukecoin-storage-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn blocks(count: usize) -> Vec<Block> {
+        let mut chain = Blockchain::new(Network::Testnet);
+        let miner = Wallet::new(Network::Testnet);
+        for _ in 1..count {
+            chain.add_block(Vec::new(), &miner).unwrap();
+        }
+        chain.chain
+    }
+
+    /// A store in `dir` holding `blocks`, closed again.
+    fn fill(dir: &Path, blocks: &[Block]) {
+        let (mut store, report) = BlockStore::open(dir).unwrap();
+        assert!(report.is_clean());
+        for block in blocks {
+            store.append(block).unwrap();
+        }
+    }
+
+    fn append_raw(dir: &Path, bytes: &[u8]) {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.join(BLOCKS_FILE))
+            .unwrap();
+        file.write_all(bytes).unwrap();
+    }
+
+    fn hashes(blocks: &[Block]) -> Vec<String> {
+        blocks.iter().map(|block| block.hash.clone()).collect()
+    }
+
+    #[test]
+    fn blocks_survive_reopening() {
+        let dir = TempDir::new("reopen");
+        let blocks = blocks(3);
+        fill(&dir.0, &blocks);
+
+        let (mut store, report) = BlockStore::open(&dir.0).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(hashes(&store.load_blocks().unwrap()), hashes(&blocks));
+    }
+
+    #[test]
+    fn repair_cuts_a_torn_record() {
+        let dir = TempDir::new("torn");
+        let blocks = blocks(3);
+        fill(&dir.0, &blocks);
+        let clean_len = fs::metadata(dir.0.join(BLOCKS_FILE)).unwrap().len();
+
+        // A crash halfway through appending the next block.
+        let record = blocks[2].to_bytes();
+        let mut torn = (record.len() as u32).to_be_bytes().to_vec();
+        torn.extend_from_slice(&record[..record.len() / 2]);
+        append_raw(&dir.0, &torn);
+
+        let (mut store, report) = BlockStore::open(&dir.0).unwrap();
+        assert_eq!(
+            report,
+            RepairReport {
+                truncated_bytes: torn.len() as u64,
+                index_rebuilt: false,
+            }
+        );
+        assert_eq!(
+            fs::metadata(dir.0.join(BLOCKS_FILE)).unwrap().len(),
+            clean_len
+        );
+        assert_eq!(hashes(&store.load_blocks().unwrap()), hashes(&blocks));
+
+        store.append(&blocks[2]).unwrap();
+        assert_eq!(store.len(), 4);
+    }
+
+    #[test]
+    fn repair_cuts_an_undecodable_record() {
+        let dir = TempDir::new("garbage");
+        let blocks = blocks(2);
+        fill(&dir.0, &blocks);
+
+        let mut garbage = 3u32.to_be_bytes().to_vec();
+        garbage.extend_from_slice(&[0xff; 3]);
+        append_raw(&dir.0, &garbage);
+
+        let (mut store, report) = BlockStore::open(&dir.0).unwrap();
+        assert_eq!(report.truncated_bytes, garbage.len() as u64);
+        assert_eq!(hashes(&store.load_blocks().unwrap()), hashes(&blocks));
+    }
+
+    #[test]
+    fn repair_rebuilds_a_stale_index() {
+        let dir = TempDir::new("index");
+        let blocks = blocks(3);
+        fill(&dir.0, &blocks);
+
+        // The block was synced but the crash came before its index entry.
+        let index = OpenOptions::new()
+            .write(true)
+            .open(dir.0.join(INDEX_FILE))
+            .unwrap();
+        index.set_len(2 * 8).unwrap();
+
+        let (mut store, report) = BlockStore::open(&dir.0).unwrap();
+        assert_eq!(
+            report,
+            RepairReport {
+                truncated_bytes: 0,
+                index_rebuilt: true,
+            }
+        );
+        assert_eq!(hashes(&store.load_blocks().unwrap()), hashes(&blocks));
+    }
+
+    #[test]
+    fn truncate_drops_the_tail() {
+        let dir = TempDir::new("truncate");
+        let blocks = blocks(3);
+        fill(&dir.0, &blocks);
+
+        let (mut store, _) = BlockStore::open(&dir.0).unwrap();
+        store.truncate(1).unwrap();
+        store.append(&blocks[1]).unwrap();
+        drop(store);
+
+        let (mut store, report) = BlockStore::open(&dir.0).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(hashes(&store.load_blocks().unwrap()), hashes(&blocks[..2]));
+    }
+
+    #[test]
+    fn write_private_replaces_the_file_and_a_stale_tmp() {
+        let dir = TempDir::new("private");
+        let path = dir.0.join("secret.key");
+        write_private(&path, b"old").unwrap();
+        fs::write(path.with_extension("tmp"), b"left by a crash").unwrap();
+
+        write_private(&path, b"new").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert!(!path.with_extension("tmp").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
@@ -0,0 +1,435 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::blockchain::{
+    check_timing, Block, BlockHeader, Blockchain, DIFFICULTY_ADJUSTMENT_INTERVAL, MEDIAN_TIME_SPAN,
+};
+use crate::pow::{bits_to_target, max_target};
+
+/// Most headers a peer sends in one response.
+pub const MAX_HEADERS_PER_RESPONSE: usize = 2000;
+/// Blocks asked for in one request during the download.
+pub const BLOCKS_PER_REQUEST: usize = 16;
+/// Most headers waiting for their blocks. Once reached, the blocks are
+/// downloaded before any more headers are asked for.
+pub const MAX_QUEUED_HEADERS: usize = 10_000;
+
+/// How many ancestors the timestamp and target rules look back on.
+const LOOKBACK: usize = if MEDIAN_TIME_SPAN > DIFFICULTY_ADJUSTMENT_INTERVAL {
+    MEDIAN_TIME_SPAN
+} else {
+    DIFFICULTY_ADJUSTMENT_INTERVAL
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncError {
+    /// The first header does not build on any block we know.
+    Disconnected,
+    InvalidHeader(&'static str),
+    UnrequestedBlock(String),
+    /// The peer sent none of the blocks asked for, so asking again would
+    /// only loop.
+    MissingBlocks,
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncError::Disconnected => write!(f, "headers do not connect to our chain"),
+            SyncError::InvalidHeader(reason) => write!(f, "invalid header: {}", reason),
+            SyncError::UnrequestedBlock(hash) => write!(f, "peer sent unrequested block {}", hash),
+            SyncError::MissingBlocks => write!(f, "peer sent none of the requested blocks"),
+        }
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+/// What the download wants from the peer next.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncStep {
+    /// Headers following this locator.
+    Headers(Vec<String>),
+    /// The full blocks with these hashes.
+    Blocks(Vec<String>),
+    Done,
+}
+
+/// Header-first download from a single peer.
+///
+/// Headers are fetched first and checked for linkage, proof of work, target
+/// and timestamp, which is cheap and lets a lying peer be caught before any
+/// block is downloaded. The blocks behind them are then fetched in batches and
+/// handed to `Blockchain::accept_block`, which does the full validation.
+#[derive(Debug)]
+pub struct HeaderSync {
+    /// Headers whose blocks are still to be requested, in chain order.
+    queued: VecDeque<BlockHeader>,
+    max_queued: usize,
+    /// Hashes requested but not yet received.
+    in_flight: Vec<String>,
+    /// Newest header accepted so far, then its ancestors, as far back as
+    /// the timestamp and target rules look.
+    recent: Vec<BlockHeader>,
+    headers_done: bool,
+    /// The queue filled up before the peer ran out of headers.
+    more_headers: bool,
+    /// Height of the peer's best header.
+    target_height: u64,
+}
+
+impl Default for HeaderSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeaderSync {
+    pub fn new() -> Self {
+        Self::with_max_queued(MAX_QUEUED_HEADERS)
+    }
+
+    pub fn with_max_queued(max_queued: usize) -> Self {
+        Self {
+            queued: VecDeque::new(),
+            max_queued,
+            in_flight: Vec::new(),
+            recent: Vec::new(),
+            headers_done: false,
+            more_headers: false,
+            target_height: 0,
+        }
+    }
+
+    pub fn target_height(&self) -> u64 {
+        self.target_height
+    }
+
+    /// Whether all headers are in and we are fetching blocks.
+    pub fn is_downloading_blocks(&self) -> bool {
+        self.headers_done
+    }
+
+    pub fn next_step(&mut self, chain: &Blockchain) -> SyncStep {
+        if !self.headers_done {
+            let mut locator = chain.locator();
+            if let Some(last) = self.recent.first() {
+                locator.insert(0, last.hash.clone());
+            }
+            return SyncStep::Headers(locator);
+        }
+
+        if !self.in_flight.is_empty() {
+            return SyncStep::Blocks(self.in_flight.clone());
+        }
+
+        let batch: Vec<String> = self
+            .queued
+            .drain(..self.queued.len().min(BLOCKS_PER_REQUEST))
+            .map(|header| header.hash)
+            .collect();
+        if batch.is_empty() {
+            if self.more_headers {
+                self.more_headers = false;
+                self.headers_done = false;
+                return self.next_step(chain);
+            }
+            return SyncStep::Done;
+        }
+
+        self.in_flight = batch.clone();
+        SyncStep::Blocks(batch)
+    }
+
+    /// Checks a batch of headers and queues the blocks behind them. A short
+    /// batch means the peer has no more. Headers past the queue's limit are
+    /// dropped and asked for again once the queue has drained.
+    pub fn add_headers(
+        &mut self,
+        headers: Vec<BlockHeader>,
+        chain: &Blockchain,
+    ) -> Result<(), SyncError> {
+        if headers.len() < MAX_HEADERS_PER_RESPONSE {
+            self.headers_done = true;
+        }
+
+        for header in headers {
+            if self.queued.len() >= self.max_queued {
+                self.headers_done = true;
+                self.more_headers = true;
+                break;
+            }
+
+            let parent_queued = self
+                .recent
+                .first()
+                .is_some_and(|last| last.hash == header.previous_hash);
+            if !parent_queued {
+                self.recent = chain.ancestor_headers(&header.previous_hash, LOOKBACK);
+            }
+            let Some(parent) = self.recent.first() else {
+                return Err(SyncError::Disconnected);
+            };
+
+            if header.height != parent.height + 1 {
+                return Err(SyncError::InvalidHeader(
+                    "height does not follow its parent",
+                ));
+            }
+            // An oversized exponent expands to the largest target, which
+            // any hash meets.
+            if bits_to_target(header.bits) > max_target() {
+                return Err(SyncError::InvalidHeader(
+                    "target is easier than the minimum difficulty",
+                ));
+            }
+            if !header.has_valid_work() {
+                return Err(SyncError::InvalidHeader("proof of work is invalid"));
+            }
+            check_timing(&header, self.recent.iter()).map_err(SyncError::InvalidHeader)?;
+
+            let known = !parent_queued && chain.get_block(&header.hash).is_some();
+            self.recent.insert(0, header.clone());
+            self.recent.truncate(LOOKBACK);
+
+            // Headers we already have blocks for only move the starting point.
+            if known {
+                continue;
+            }
+
+            self.target_height = self.target_height.max(header.height);
+            self.queued.push_back(header);
+        }
+
+        Ok(())
+    }
+
+    /// Marks blocks as received, returning them in the order they were
+    /// requested so each one's parent comes first. A response without any
+    /// of them is an error; the rest of a partial one is asked for again.
+    pub fn take_blocks(&mut self, blocks: Vec<Block>) -> Result<Vec<Block>, SyncError> {
+        if blocks.is_empty() {
+            return Err(SyncError::MissingBlocks);
+        }
+        if let Some(block) = blocks
+            .iter()
+            .find(|block| !self.in_flight.contains(&block.hash))
+        {
+            return Err(SyncError::UnrequestedBlock(block.hash.clone()));
+        }
+
+        let mut blocks = blocks;
+        blocks.sort_by_key(|block| self.in_flight.iter().position(|hash| hash == &block.hash));
+        self.in_flight
+            .retain(|hash| !blocks.iter().any(|block| &block.hash == hash));
+
+        Ok(blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Network;
+    use crate::blockchain::MAX_FUTURE_BLOCK_TIME;
+    use crate::wallet::Wallet;
+    use chrono::Utc;
+
+    /// Our chain at genesis and a peer's chain `length` blocks long.
+    fn chains(length: usize) -> (Blockchain, Blockchain) {
+        let mut theirs = Blockchain::new(Network::Testnet);
+        let miner = Wallet::new(Network::Testnet);
+        for _ in 1..length {
+            theirs.add_block(Vec::new(), &miner).unwrap();
+        }
+        (Blockchain::new(Network::Testnet), theirs)
+    }
+
+    fn blocks(chain: &Blockchain, hashes: &[String]) -> Vec<Block> {
+        hashes
+            .iter()
+            .map(|hash| chain.get_block(hash).unwrap().clone())
+            .collect()
+    }
+
+    /// Finds a nonce for `header` as it now is.
+    fn remine(mut header: BlockHeader) -> BlockHeader {
+        header.nonce = 0;
+        loop {
+            header.hash = hex::encode(header.calculate_hash());
+            if header.has_valid_work() {
+                return header;
+            }
+            header.nonce += 1;
+        }
+    }
+
+    /// Downloads everything `sync` asks for from `theirs` into `ours`.
+    fn run(sync: &mut HeaderSync, ours: &mut Blockchain, theirs: &Blockchain) {
+        loop {
+            match sync.next_step(ours) {
+                SyncStep::Headers(locator) => sync
+                    .add_headers(
+                        theirs.headers_after(&locator, MAX_HEADERS_PER_RESPONSE),
+                        ours,
+                    )
+                    .unwrap(),
+                SyncStep::Blocks(hashes) => {
+                    for block in sync.take_blocks(blocks(theirs, &hashes)).unwrap() {
+                        ours.accept_block(block).unwrap();
+                    }
+                }
+                SyncStep::Done => return,
+            }
+        }
+    }
+
+    #[test]
+    fn downloads_headers_then_blocks() {
+        let (mut ours, theirs) = chains(BLOCKS_PER_REQUEST + 4);
+        let mut sync = HeaderSync::new();
+
+        let SyncStep::Headers(locator) = sync.next_step(&ours) else {
+            panic!("sync starts with headers");
+        };
+        sync.add_headers(
+            theirs.headers_after(&locator, MAX_HEADERS_PER_RESPONSE),
+            &ours,
+        )
+        .unwrap();
+        assert!(sync.is_downloading_blocks());
+        assert_eq!(sync.target_height(), theirs.tip().height);
+
+        while let SyncStep::Blocks(hashes) = sync.next_step(&ours) {
+            assert!(hashes.len() <= BLOCKS_PER_REQUEST);
+            // The peer sends the batch in reverse; parents must still come first.
+            let mut batch = blocks(&theirs, &hashes);
+            batch.reverse();
+            for block in sync.take_blocks(batch).unwrap() {
+                ours.accept_block(block).unwrap();
+            }
+        }
+
+        assert_eq!(ours.tip().hash, theirs.tip().hash);
+    }
+
+    #[test]
+    fn response_without_requested_blocks_is_an_error() {
+        let (ours, theirs) = chains(4);
+        let mut sync = HeaderSync::new();
+        sync.add_headers(theirs.headers_after(&ours.locator(), 10), &ours)
+            .unwrap();
+
+        let SyncStep::Blocks(hashes) = sync.next_step(&ours) else {
+            panic!("headers are done");
+        };
+        assert_eq!(
+            sync.take_blocks(Vec::new()).unwrap_err(),
+            SyncError::MissingBlocks
+        );
+
+        // A partial answer keeps the rest in flight until it stops helping.
+        let first = blocks(&theirs, &hashes[..1]);
+        assert_eq!(sync.take_blocks(first).unwrap().len(), 1);
+        assert_eq!(
+            sync.next_step(&ours),
+            SyncStep::Blocks(hashes[1..].to_vec())
+        );
+        assert_eq!(
+            sync.take_blocks(Vec::new()).unwrap_err(),
+            SyncError::MissingBlocks
+        );
+    }
+
+    #[test]
+    fn unrequested_or_disconnected_data_is_an_error() {
+        let (ours, theirs) = chains(3);
+        let (_, other) = chains(3);
+        let mut sync = HeaderSync::new();
+
+        let mut headers = theirs.headers_after(&ours.locator(), 10);
+        assert_eq!(
+            HeaderSync::new().add_headers(headers[1..].to_vec(), &ours),
+            Err(SyncError::Disconnected)
+        );
+
+        headers[1].nonce += 1;
+        assert!(matches!(
+            HeaderSync::new().add_headers(headers.clone(), &ours),
+            Err(SyncError::InvalidHeader(_))
+        ));
+
+        sync.add_headers(headers[..1].to_vec(), &ours).unwrap();
+        sync.next_step(&ours);
+        assert_eq!(
+            sync.take_blocks(vec![other.chain[1].clone()]).unwrap_err(),
+            SyncError::UnrequestedBlock(other.chain[1].hash.clone())
+        );
+    }
+
+    #[test]
+    fn header_with_inflated_bits_is_rejected() {
+        let (ours, theirs) = chains(3);
+        let mut headers = theirs.headers_after(&ours.locator(), 10);
+
+        // Its exponent makes the target the largest possible, so the hash
+        // meets it whatever the nonce.
+        headers[0].bits = 0x2200ffff;
+        headers[0] = remine(headers[0].clone());
+        assert!(headers[0].has_valid_work());
+
+        assert_eq!(
+            HeaderSync::new().add_headers(headers, &ours),
+            Err(SyncError::InvalidHeader(
+                "target is easier than the minimum difficulty"
+            ))
+        );
+    }
+
+    #[test]
+    fn headers_must_follow_the_target_and_timestamp_rules() {
+        let (ours, theirs) = chains(3);
+        let headers = theirs.headers_after(&ours.locator(), 10);
+        let rejection = |edit: &dyn Fn(&mut BlockHeader)| {
+            let mut headers = headers.clone();
+            edit(&mut headers[1]);
+            headers[1] = remine(headers[1].clone());
+            HeaderSync::new().add_headers(headers, &ours).unwrap_err()
+        };
+
+        assert_eq!(
+            rejection(&|header| header.bits = 0x1f7fffff),
+            SyncError::InvalidHeader("unexpected target")
+        );
+        assert_eq!(
+            rejection(&|header| header.timestamp = headers[0].timestamp),
+            SyncError::InvalidHeader("timestamp is before median time past")
+        );
+        assert_eq!(
+            rejection(&|header| {
+                header.timestamp = Utc::now().timestamp() + MAX_FUTURE_BLOCK_TIME + 60
+            }),
+            SyncError::InvalidHeader("timestamp is too far in the future")
+        );
+    }
+
+    #[test]
+    fn full_queue_pauses_headers_until_blocks_are_in() {
+        let (mut ours, theirs) = chains(8);
+        let mut sync = HeaderSync::with_max_queued(3);
+
+        let SyncStep::Headers(locator) = sync.next_step(&ours) else {
+            panic!("sync starts with headers");
+        };
+        sync.add_headers(
+            theirs.headers_after(&locator, MAX_HEADERS_PER_RESPONSE),
+            &ours,
+        )
+        .unwrap();
+        assert!(sync.is_downloading_blocks());
+        assert_eq!(sync.target_height(), 3);
+
+        run(&mut sync, &mut ours, &theirs);
+        assert_eq!(ours.tip().hash, theirs.tip().hash);
+    }
+}
@@ -3,12 +3,20 @@ use secp256k1::{ecdsa::Signature, Message, PublicKey, Secp256k1, SecretKey};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
-#[derive(Serialize, Deserialize, Debug)]
+use crate::address::{Address, Network};
+use crate::encoding::{Decode, DecodeError, Decoder, Encode, Encoder};
+
+/// Sender of coinbase transactions. Not valid base58, so it can never be a
+/// real address.
+pub const COINBASE_SENDER: &str = "0";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Transaction {
     pub index: u64,
     pub sender: String,
     pub recipient: String,
     pub amount: u64,
+    pub fee: u64,
     pub memo: Option<String>,
     pub timestamp: i64,
     pub signature: Option<Signature>,
@@ -21,6 +29,7 @@ impl Transaction {
         sender: String,
         recipient: String,
         amount: u64,
+        fee: u64,
         memo: Option<String>,
         public_key: PublicKey,
     ) -> Self {
@@ -29,18 +38,62 @@ impl Transaction {
             sender,
             recipient,
             amount,
+            fee,
             memo,
             timestamp: Utc::now().timestamp(),
             signature: None,
             public_key,
         }
     }
+
+    /// Block reward paid to `recipient`, created by the miner of block `height`.
+    pub fn coinbase(height: u64, recipient: String, amount: u64, public_key: PublicKey) -> Self {
+        Self::new(
+            height,
+            COINBASE_SENDER.to_string(),
+            recipient,
+            amount,
+            0,
+            None,
+            public_key,
+        )
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        self.sender == COINBASE_SENDER
+    }
+
+    /// Encoded size in bytes, used to weigh the fee against block space.
+    pub fn size(&self) -> usize {
+        self.to_bytes().len()
+    }
+
+    /// Amount plus fee, i.e. what the sender is debited.
+    pub fn total_cost(&self) -> Option<u64> {
+        self.amount.checked_add(self.fee)
+    }
+
+    /// Every field except the signature, in canonical order.
+    fn encode_unsigned(&self, encoder: &mut Encoder) {
+        encoder
+            .u64(self.index)
+            .str(&self.sender)
+            .str(&self.recipient)
+            .u64(self.amount)
+            .u64(self.fee)
+            .option(self.memo.as_deref(), |encoder, memo| {
+                encoder.str(memo);
+            })
+            .i64(self.timestamp)
+            .fixed(&self.public_key.serialize());
+    }
+
     pub fn hash_without_signature(&self) -> Option<[u8; 32]> {
+        let mut encoder = Encoder::new();
+        self.encode_unsigned(&mut encoder);
+
         let mut hasher = Sha256::new();
-        hasher.update(format!(
-            "{}{}{}{}{}{}",
-            self.index, self.sender, self.recipient, self.amount, self.timestamp, self.public_key
-        ));
+        hasher.update(encoder.finish());
 
         let bytes: [u8; 32] = hasher.finalize().into();
 
@@ -48,22 +101,51 @@ impl Transaction {
     }
 
     pub fn sign(&mut self, private_key: &SecretKey) {
-        let hash: Option<[u8; 32]> = self.hash_without_signature();
-        if hash.is_none() {
-            ()
-        }
+        let Some(hash) = self.hash_without_signature() else {
+            return;
+        };
 
-        let msg: Message = Message::from_digest(hash.unwrap());
+        let msg: Message = Message::from_digest(hash);
         let signature: Signature = private_key.sign_ecdsa(msg);
 
         self.signature = Some(signature);
     }
 
+    /// Address whose key must sign: the recipient for a coinbase, otherwise
+    /// the sender.
+    pub fn signer(&self) -> &str {
+        if self.is_coinbase() {
+            &self.recipient
+        } else {
+            &self.sender
+        }
+    }
+
+    /// Network both the signer and the recipient are on, or `None` if
+    /// either address is malformed or they disagree.
+    pub fn network(&self) -> Option<Network> {
+        let signer = self.signer().parse::<Address>().ok()?;
+        let recipient = self.recipient.parse::<Address>().ok()?;
+        (signer.network() == recipient.network()).then(|| recipient.network())
+    }
+
+    /// Checks that both addresses are well formed and on the same network,
+    /// that the public key owns the signer's address, and the signature.
     pub fn verify(&self) -> bool {
         if self.signature.is_none() {
             return false;
         }
 
+        let (Ok(signer), Ok(recipient)) = (
+            self.signer().parse::<Address>(),
+            self.recipient.parse::<Address>(),
+        ) else {
+            return false;
+        };
+        if signer.network() != recipient.network() || !signer.is_owned_by(&self.public_key) {
+            return false;
+        }
+
         let hash: Option<[u8; 32]> = self.hash_without_signature();
         if hash.is_none() {
             return false;
@@ -77,3 +159,147 @@ impl Transaction {
             .is_ok()
     }
 }
+
+impl Encode for Transaction {
+    fn encode(&self, encoder: &mut Encoder) {
+        self.encode_unsigned(encoder);
+        encoder.option(self.signature, |encoder, signature| {
+            encoder.fixed(&signature.serialize_compact());
+        });
+    }
+}
+
+impl Decode for Transaction {
+    fn decode(decoder: &mut Decoder) -> Result<Self, DecodeError> {
+        Ok(Self {
+            index: decoder.u64()?,
+            sender: decoder.str()?,
+            recipient: decoder.str()?,
+            amount: decoder.u64()?,
+            fee: decoder.u64()?,
+            memo: decoder.option(Decoder::str)?,
+            timestamp: decoder.i64()?,
+            public_key: PublicKey::from_slice(decoder.fixed(33)?)
+                .map_err(|_| DecodeError::InvalidValue("public key"))?,
+            signature: decoder.option(|decoder| {
+                Signature::from_compact(decoder.fixed(64)?)
+                    .map_err(|_| DecodeError::InvalidValue("signature"))
+            })?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Network;
+    use crate::wallet::Wallet;
+
+    fn signed(memo: Option<&str>) -> Transaction {
+        let mut wallet = Wallet::new(Network::Testnet);
+        let from = wallet.address().to_string();
+        let to = Wallet::new(Network::Testnet).address().to_string();
+        wallet
+            .create_transaction(&from, to, 25, 2, memo.map(str::to_string))
+            .unwrap()
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        for tx in [signed(None), signed(Some("rent"))] {
+            let bytes = tx.to_bytes();
+            let decoded = Transaction::from_bytes(&bytes).unwrap();
+
+            assert_eq!(decoded.to_bytes(), bytes);
+            assert_eq!(decoded.memo, tx.memo);
+            assert_eq!(decoded.signature, tx.signature);
+            assert!(decoded.verify());
+        }
+    }
+
+    #[test]
+    fn unsigned_transaction_round_trips() {
+        let mut tx = signed(None);
+        tx.signature = None;
+
+        let decoded = Transaction::from_bytes(&tx.to_bytes()).unwrap();
+        assert!(decoded.signature.is_none());
+        assert_eq!(decoded.to_bytes(), tx.to_bytes());
+    }
+
+    #[test]
+    fn memo_is_part_of_the_hash() {
+        let tx = signed(Some("rent"));
+        let mut changed = tx.clone();
+        changed.memo = Some("gift".to_string());
+        let mut removed = tx.clone();
+        removed.memo = None;
+
+        assert_ne!(
+            tx.hash_without_signature(),
+            changed.hash_without_signature()
+        );
+        assert_ne!(
+            tx.hash_without_signature(),
+            removed.hash_without_signature()
+        );
+        assert!(!changed.verify());
+    }
+
+    #[test]
+    fn truncated_or_padded_bytes_are_rejected() {
+        let bytes = signed(Some("rent")).to_bytes();
+
+        for len in 0..bytes.len() {
+            assert!(Transaction::from_bytes(&bytes[..len]).is_err(), "{}", len);
+        }
+
+        let mut padded = bytes;
+        padded.push(0);
+        assert_eq!(
+            Transaction::from_bytes(&padded).unwrap_err(),
+            DecodeError::TrailingBytes
+        );
+    }
+
+    #[test]
+    fn sender_must_be_the_signing_key() {
+        let mallory = Wallet::new(Network::Testnet);
+        let victim = Wallet::new(Network::Testnet);
+        let (mallory_key, victim_key) = (&mallory.keys()[0], &victim.keys()[0]);
+
+        // Mallory's own key and signature, but the victim named as sender.
+        let mut tx = Transaction::new(
+            0,
+            victim_key.address.clone(),
+            mallory_key.address.clone(),
+            25,
+            0,
+            None,
+            mallory_key.public_key,
+        );
+        tx.sign(&mallory_key.private_key);
+        assert!(!tx.verify());
+
+        // The victim's public key, but signed by Mallory.
+        tx.public_key = victim_key.public_key;
+        tx.sign(&mallory_key.private_key);
+        assert!(!tx.verify());
+
+        tx.sign(&victim_key.private_key);
+        assert!(tx.verify());
+    }
+
+    #[test]
+    fn coinbase_must_be_signed_by_its_recipient() {
+        let miner = Wallet::new(Network::Testnet);
+        let other = Wallet::new(Network::Testnet);
+
+        let mut coinbase = miner.create_coinbase(1, 50);
+        assert!(coinbase.verify());
+
+        coinbase.recipient = other.address().to_string();
+        coinbase.sign(&miner.keys()[0].private_key);
+        assert!(!coinbase.verify());
+    }
+}
@@ -1,57 +1,289 @@
-use base58::ToBase58;
-use secp256k1::{rand, PublicKey, Secp256k1, SecretKey};
-use sha2::{Digest, Sha256};
+use bip39::Mnemonic;
+use secp256k1::{
+    rand::{self, RngCore},
+    PublicKey, Secp256k1, SecretKey,
+};
+use std::path::Path;
 
+use crate::address::{Address, Network};
+use crate::hd::{ExtendedKey, HARDENED};
+use crate::keystore::{Keystore, KeystoreError};
+use crate::ledger::Ledger;
+use crate::mempool::Mempool;
 use crate::transaction::Transaction;
 
-#[derive(Debug)]
-pub struct Wallet {
+/// BIP44 coin type. 1 is the value SLIP-44 reserves for test networks.
+const COIN_TYPE: u32 = 1;
+
+/// Unused addresses in a row after which scanning stops, as in BIP44.
+pub const GAP_LIMIT: usize = 20;
+
+/// One derived key pair and the index its next transaction must carry.
+#[derive(Debug, Clone)]
+pub struct WalletKey {
     pub private_key: SecretKey,
     pub public_key: PublicKey,
     pub address: String,
     pub tx_height: u64,
 }
 
-impl Wallet {
-    pub fn new() -> Self {
-        let secp = Secp256k1::new();
+impl WalletKey {
+    fn new(private_key: SecretKey, network: Network) -> Self {
+        let public_key = private_key.public_key(&Secp256k1::new());
 
-        let (private_key, public_key) = secp.generate_keypair(&mut rand::rng());
-
-        let address = Self::public_key_to_address(&public_key);
-
-        Wallet {
+        Self {
             private_key,
             public_key,
-            address,
+            address: Address::from_public_key(&public_key, network).to_string(),
             tx_height: 0,
         }
     }
 
-    fn public_key_to_address(pubkey: &PublicKey) -> String {
-        let pubkey_bytes: [u8; 33] = pubkey.serialize();
+    /// Whether the chain has seen this address hold coins or spend them.
+    pub fn is_used(&self, ledger: &Ledger) -> bool {
+        ledger.balance(&self.address) > 0 || ledger.next_index(&self.address) > 0
+    }
+}
+
+/// Hierarchical deterministic wallet.
+///
+/// Every key comes from a BIP39 mnemonic along the BIP44 receive chain
+/// `m/44'/1'/0'/0/i`, so the phrase alone restores the wallet. `keys` holds
+/// the used keys plus the ones handed out since; `receive` points at the
+/// address to give the next payer.
+#[derive(Debug)]
+pub struct Wallet {
+    network: Network,
+    mnemonic: Mnemonic,
+    chain: ExtendedKey,
+    keys: Vec<WalletKey>,
+    receive: usize,
+}
+
+impl Wallet {
+    /// Wallet with a fresh 12-word mnemonic.
+    pub fn new(network: Network) -> Self {
+        let mut entropy = [0u8; 16];
+        rand::rng().fill_bytes(&mut entropy);
+
+        let mnemonic =
+            Mnemonic::from_entropy(&entropy).expect("16 bytes is a valid entropy length");
+        Self::from_mnemonic(mnemonic, network)
+    }
+
+    /// Restores the wallet written down as `phrase`.
+    pub fn from_phrase(phrase: &str, network: Network) -> Result<Self, bip39::Error> {
+        Ok(Self::from_mnemonic(Mnemonic::parse(phrase)?, network))
+    }
+
+    pub fn from_mnemonic(mnemonic: Mnemonic, network: Network) -> Self {
+        let chain = ExtendedKey::master(&mnemonic.to_seed(""))
+            .and_then(|master| master.derive(&[44 | HARDENED, COIN_TYPE | HARDENED, HARDENED, 0]))
+            .expect("a BIP39 seed yields a valid account key");
+
+        let mut wallet = Self {
+            network,
+            mnemonic,
+            chain,
+            keys: Vec::new(),
+            receive: 0,
+        };
+        let key = wallet.derive(0);
+        wallet.keys.push(key);
+        wallet
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// The recovery phrase.
+    pub fn mnemonic(&self) -> String {
+        self.mnemonic.to_string()
+    }
+
+    /// Address to hand out for the next payment.
+    pub fn address(&self) -> &str {
+        &self.keys[self.receive].address
+    }
+
+    pub fn keys(&self) -> &[WalletKey] {
+        &self.keys
+    }
+
+    pub fn key(&self, address: &str) -> Option<&WalletKey> {
+        self.keys.iter().find(|key| key.address == address)
+    }
+
+    pub fn owns(&self, address: &str) -> bool {
+        self.key(address).is_some()
+    }
+
+    /// Sum of the balances of every known key.
+    pub fn balance(&self, ledger: &Ledger) -> u64 {
+        self.keys
+            .iter()
+            .map(|key| ledger.balance(&key.address))
+            .fold(0, u64::saturating_add)
+    }
+
+    /// Balance left across all keys once pooled transactions are mined.
+    pub fn available_balance(&self, mempool: &Mempool, ledger: &Ledger) -> u64 {
+        self.keys
+            .iter()
+            .map(|key| mempool.available_balance(&key.address, ledger))
+            .fold(0, u64::saturating_add)
+    }
+
+    /// Receive key `index`, skipping the indices BIP32 cannot derive.
+    fn derive(&self, index: usize) -> WalletKey {
+        let child = (index as u32..HARDENED)
+            .find_map(|index| self.chain.child(index))
+            .expect("the receive chain has derivable keys");
+        WalletKey::new(child.secret_key, self.network)
+    }
+
+    /// Moves on to an address no one has been given yet.
+    pub fn new_receive_address(&mut self) -> &str {
+        let key = self.derive(self.keys.len());
+        self.keys.push(key);
+        self.receive = self.keys.len() - 1;
+        self.address()
+    }
+
+    /// Refreshes every key's transaction index, discovers used keys past the
+    /// known ones until `GAP_LIMIT` unused addresses in a row, and moves the
+    /// receive address past any that has been paid.
+    pub fn sync(&mut self, ledger: &Ledger) {
+        for key in &mut self.keys {
+            key.tx_height = ledger.next_index(&key.address);
+        }
 
-        let sha256_hash = Sha256::digest(&pubkey_bytes);
+        let mut gap = Vec::new();
+        while gap.len() < GAP_LIMIT {
+            let mut key = self.derive(self.keys.len() + gap.len());
+            key.tx_height = ledger.next_index(&key.address);
 
-        sha256_hash.to_base58()
+            let used = key.is_used(ledger);
+            gap.push(key);
+            if used {
+                self.keys.append(&mut gap);
+            }
+        }
+
+        if let Some(last_used) = self.keys.iter().rposition(|key| key.is_used(ledger)) {
+            self.receive = self.receive.max(last_used + 1);
+        }
+        if self.receive == self.keys.len() {
+            let key = gap
+                .into_iter()
+                .next()
+                .unwrap_or_else(|| self.derive(self.receive));
+            self.keys.push(key);
+        }
     }
 
+    /// Numbers each key's next transaction after its pooled ones.
+    pub fn sync_pending(&mut self, mempool: &Mempool, ledger: &Ledger) {
+        for key in &mut self.keys {
+            key.tx_height = mempool.next_index(&key.address, ledger);
+        }
+    }
+
+    /// Signs a payment from the key owning `from`, or `None` if this wallet
+    /// does not own it.
     pub fn create_transaction(
         &mut self,
+        from: &str,
         recipient: String,
         amount: u64,
+        fee: u64,
         memo: Option<String>,
-    ) -> Transaction {
+    ) -> Option<Transaction> {
+        let key = self.keys.iter_mut().find(|key| key.address == from)?;
+
         let mut tx = Transaction::new(
-            self.tx_height,
-            self.address.clone(),
+            key.tx_height,
+            key.address.clone(),
             recipient,
             amount,
+            fee,
             memo,
-            self.public_key,
+            key.public_key,
         );
-        tx.sign(&self.private_key);
-        self.tx_height += 1;
+        tx.sign(&key.private_key);
+        key.tx_height += 1;
+        Some(tx)
+    }
+
+    /// Coinbase paying the receive address.
+    pub fn create_coinbase(&self, height: u64, amount: u64) -> Transaction {
+        let key = &self.keys[self.receive];
+        let mut tx = Transaction::coinbase(height, key.address.clone(), amount, key.public_key);
+        tx.sign(&key.private_key);
         tx
     }
+
+    /// Encrypts the mnemonic under `passphrase` and writes it to `path`.
+    pub fn save(&self, path: impl AsRef<Path>, passphrase: &str) -> Result<(), KeystoreError> {
+        Keystore::seal(self.mnemonic().as_bytes(), passphrase)?.write(path)
+    }
+
+    /// Reads the keystore at `path` and restores the wallet from the
+    /// mnemonic inside. Call `sync` afterwards to rediscover used keys.
+    pub fn load(
+        path: impl AsRef<Path>,
+        passphrase: &str,
+        network: Network,
+    ) -> Result<Self, KeystoreError> {
+        let secret = Keystore::read(path)?.open(passphrase)?;
+        let phrase = String::from_utf8(secret).map_err(|_| KeystoreError::InvalidSecret)?;
+        Self::from_phrase(&phrase, network).map_err(|_| KeystoreError::InvalidSecret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genesis::{TESTNET, TESTNET_FAUCET_PHRASE};
+
+    #[test]
+    fn phrase_restores_the_same_keys() {
+        let mut wallet = Wallet::new(Network::Testnet);
+        wallet.new_receive_address();
+
+        let mut restored = Wallet::from_phrase(&wallet.mnemonic(), Network::Testnet).unwrap();
+        restored.new_receive_address();
+
+        let addresses = |wallet: &Wallet| -> Vec<String> {
+            wallet
+                .keys()
+                .iter()
+                .map(|key| key.address.clone())
+                .collect()
+        };
+        assert_eq!(addresses(&restored), addresses(&wallet));
+    }
+
+    #[test]
+    fn test_phrase_owns_the_testnet_faucet() {
+        let wallet = Wallet::from_phrase(TESTNET_FAUCET_PHRASE, Network::Testnet).unwrap();
+        assert_eq!(wallet.address(), TESTNET.allocations[0].address);
+    }
+
+    #[test]
+    fn sync_discovers_used_keys_within_the_gap_limit() {
+        let wallet = Wallet::from_phrase(TESTNET_FAUCET_PHRASE, Network::Testnet).unwrap();
+        let far = wallet.derive(GAP_LIMIT - 1).address;
+
+        let mut ledger = Ledger::new();
+        ledger.credit(&far, 5).unwrap();
+        let mut restored = Wallet::from_phrase(TESTNET_FAUCET_PHRASE, Network::Testnet).unwrap();
+        restored.sync(&ledger);
+
+        assert!(restored.owns(&far));
+        assert_eq!(restored.balance(&ledger), 5);
+        assert_eq!(restored.keys().len(), GAP_LIMIT + 1);
+        assert_ne!(restored.address(), far);
+    }
 }